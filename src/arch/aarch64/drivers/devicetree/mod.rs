@@ -1,37 +1,114 @@
-use hermit_dtb::{Dtb, EnumSubnodesIter};
+mod node;
+
+pub use node::{Cells, Node, Property, Reg, StringList};
+
+use core::ops::Range;
 use spin::Mutex;
 
-pub static DEVICE_TREE: Mutex<Option<Dtb>> = Mutex::new(None);
+// Location and size of the blob currently in use.
+static BLOB: Mutex<Range<usize>> = Mutex::new(0..0);
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_SIZE: usize = 40;
+
+/// Validates the flattened device tree header at `base` and returns the blob's `totalsize`.
+pub fn blob_size(base: usize) -> Result<usize, &'static str> {
+    if base == 0 || base & 7 != 0 {
+        return Err("Device tree blob is not 8 byte aligned");
+    }
 
-pub fn init(base: u64) {
-    let mut device_tree = DEVICE_TREE.lock();
+    let header = unsafe { core::slice::from_raw_parts(base as *const u8, FDT_HEADER_SIZE) };
+    let read_be32 =
+        |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+
+    if read_be32(0) != FDT_MAGIC {
+        return Err("Invalid device tree magic");
+    }
+    // The structure block layout used by node.rs was introduced with version 16.
+    if read_be32(20) < 16 {
+        return Err("Unsupported device tree version");
+    }
+
+    let totalsize = read_be32(4) as usize;
+    if totalsize < FDT_HEADER_SIZE {
+        return Err("Invalid device tree size");
+    }
+
+    Ok(totalsize)
+}
 
-    *device_tree = Some(unsafe {
-        Dtb::from_raw(sptr::from_exposed_addr(base as usize)).expect("Error Initializing DT")
-    });
+pub fn init(base: usize) {
+    let size = blob_size(base).expect("Error Initializing DT");
+    *BLOB.lock() = base..base + size;
 }
 
-pub fn update_base_address(new_base: u32) {
-    let mut device_tree = DEVICE_TREE.lock();
-    *device_tree =
-        Some(unsafe { Dtb::from_raw(sptr::from_exposed_addr(new_base as usize)).unwrap() });
+pub fn update_base_address(new_base: usize) {
+    let mut blob = BLOB.lock();
+    *blob = new_base..new_base + blob.len();
 }
 
-pub fn get_property<'a>(path: &'a str, property: &'a str) -> Option<&'a [u8]> {
-    DEVICE_TREE
-        .lock()
-        .as_ref()
-        .unwrap()
-        .get_property(path, property)
+/// Address range of the blob in use.
+pub fn blob_range() -> Range<usize> {
+    BLOB.lock().clone()
 }
 
-pub fn enum_subnodes(path: &str) -> EnumSubnodesIter {
-    DEVICE_TREE.lock().as_ref().unwrap().enum_subnodes(path)
+pub fn get_property(path: &str, property: &str) -> Option<&'static [u8]> {
+    Node::find(path)?
+        .property(property)
+        .map(|property| property.value)
 }
 
-/// Parse one big-endian u64 from a byte slice.
-/// Returns (value, remaining_bytes).
-pub fn dt_read_u64(bytes: &[u8]) -> (usize, &[u8]) {
-    let (slice, rest) = bytes.split_at(core::mem::size_of::<u64>());
-    (u64::from_be_bytes(slice.try_into().unwrap()) as usize, rest)
+/// Names of the child nodes of `path`.
+pub fn enum_subnodes(path: &str) -> impl Iterator<Item = &'static str> {
+    Node::find(path)
+        .into_iter()
+        .flat_map(|node| node.children())
+        .map(|node| node.name())
+}
+
+/// Physical range of the initial ramdisk passed in `/chosen`, if any.
+pub fn initrd_range() -> Option<Range<usize>> {
+    let chosen = Node::find("/chosen")?;
+    let start = chosen.property("linux,initrd-start")?.as_usize()?;
+    let end = chosen.property("linux,initrd-end")?.as_usize()?;
+    (start < end).then_some(start..end)
+}
+
+/// Physical ranges the device tree keeps from the kernel: the `/memreserve/` entries of the memory
+/// reservation block and the `reg` ranges of the `/reserved-memory` nodes. Firmware carve-outs and
+/// spin tables are described this way.
+pub fn reserved_memory() -> impl Iterator<Item = Range<usize>> {
+    memreserve().chain(
+        Node::find("/reserved-memory")
+            .into_iter()
+            .flat_map(|node| node.children())
+            .filter(|node| node.is_enabled())
+            // Nodes with only a `size` are for the kernel to place, so nothing is there yet.
+            .flat_map(|node| node.reg().into_iter().flatten().filter_map(Result::ok))
+            .map(|reg| reg.address..reg.address.saturating_add(reg.size)),
+    )
+}
+
+/// Entries of the memory reservation block, which ends with an entry of size 0.
+fn memreserve() -> impl Iterator<Item = Range<usize>> {
+    let blob = blob_range();
+    let data: &[u8] = match blob.is_empty() {
+        true => &[],
+        false => unsafe { core::slice::from_raw_parts(blob.start as *const u8, blob.len()) },
+    };
+    let read_be = |offset: usize, len: usize| {
+        let bytes = data.get(offset..offset + len)?;
+        let value = bytes
+            .iter()
+            .fold(0, |value, &byte| value << 8 | byte as usize);
+        Some(value)
+    };
+
+    let mut offset = read_be(16, 4).unwrap_or(data.len());
+    core::iter::from_fn(move || {
+        let address = read_be(offset, 8)?;
+        let size = read_be(offset + 8, 8).filter(|&size| size != 0)?;
+        offset += 16;
+        Some(address..address.saturating_add(size))
+    })
 }
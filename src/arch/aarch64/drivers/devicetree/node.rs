@@ -0,0 +1,536 @@
+// Typed access to the flattened device tree (Devicetree Specification v0.4, chapter 5)
+//
+// A `Node` is the offset of its FDT_BEGIN_NODE token in the blob, so handles stay valid when the
+// blob moves. Names and property values borrow from the blob itself.
+
+use super::blob_range;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+const MAX_DEPTH: usize = 32;
+
+const DEFAULT_ADDRESS_CELLS: usize = 2;
+const DEFAULT_SIZE_CELLS: usize = 1;
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[derive(Copy, Clone)]
+struct Blob {
+    data: &'static [u8],
+    struct_start: usize,
+    struct_end: usize,
+    strings_start: usize,
+    strings_end: usize,
+}
+
+enum Token {
+    BeginNode(&'static str),
+    EndNode,
+    Prop(Property),
+    End,
+}
+
+impl Blob {
+    fn current() -> Self {
+        let range = blob_range();
+        assert!(!range.is_empty(), "Device tree not initialized");
+
+        let data = unsafe { core::slice::from_raw_parts(range.start as *const u8, range.len()) };
+        let header = |offset: usize| {
+            u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+        };
+
+        Self {
+            data,
+            struct_start: header(8),
+            struct_end: header(8) + header(36),
+            strings_start: header(12),
+            strings_end: header(12) + header(32),
+        }
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn cstr_at(&self, offset: usize, end: usize) -> Option<&'static str> {
+        let bytes = self.data.get(offset..end)?;
+        let len = bytes.iter().position(|&c| c == 0)?;
+        core::str::from_utf8(&bytes[..len]).ok()
+    }
+
+    /// Reads the token at `offset`, skipping NOPs. Returns the token's own offset, the token and
+    /// the offset of the next one. Malformed input ends the walk.
+    fn token(&self, mut offset: usize) -> (usize, Token, usize) {
+        loop {
+            let start = offset;
+            let Some(tag) = self.u32_at(offset).filter(|_| offset < self.struct_end) else {
+                return (start, Token::End, start);
+            };
+            offset += 4;
+
+            let token = match tag {
+                FDT_NOP => continue,
+                FDT_BEGIN_NODE => match self.cstr_at(offset, self.struct_end) {
+                    Some(name) => {
+                        offset = align4(offset + name.len() + 1);
+                        Token::BeginNode(name)
+                    }
+                    None => Token::End,
+                },
+                FDT_END_NODE => Token::EndNode,
+                FDT_PROP => {
+                    let value = self.u32_at(offset).zip(self.u32_at(offset + 4)).and_then(
+                        |(len, name_offset)| {
+                            let value_start = offset + 8;
+                            let value = self.data.get(value_start..value_start + len as usize)?;
+                            let name = self.cstr_at(
+                                self.strings_start + name_offset as usize,
+                                self.strings_end,
+                            )?;
+                            offset = align4(value_start + len as usize);
+                            Some(Property { name, value })
+                        },
+                    );
+                    match value {
+                        Some(property) => Token::Prop(property),
+                        None => Token::End,
+                    }
+                }
+                _ => Token::End,
+            };
+            return (start, token, offset);
+        }
+    }
+
+    /// Offset just past the FDT_END_NODE closing the node at `offset`.
+    fn node_end(&self, offset: usize) -> usize {
+        let mut depth = 0usize;
+        let mut offset = offset;
+        loop {
+            let (_, token, next) = self.token(offset);
+            match token {
+                Token::BeginNode(_) => depth += 1,
+                Token::EndNode => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return next;
+                    }
+                }
+                Token::Prop(_) => {}
+                Token::End => return next,
+            }
+            offset = next;
+        }
+    }
+}
+
+/// A property name and its raw value.
+#[derive(Copy, Clone)]
+pub struct Property {
+    pub name: &'static str,
+    pub value: &'static [u8],
+}
+
+impl Property {
+    pub fn as_u32(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.value.try_into().ok()?))
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.value.try_into().ok()?))
+    }
+
+    /// A number stored in one or two cells, as boot loaders do for `/chosen` properties.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self.value.len() {
+            4 => self.as_u32().map(|value| value as usize),
+            8 => self.as_u64().map(|value| value as usize),
+            _ => None,
+        }
+    }
+
+    /// The first string of the value.
+    pub fn as_str(&self) -> Option<&'static str> {
+        let len = self.value.iter().position(|&c| c == 0)?;
+        core::str::from_utf8(&self.value[..len]).ok()
+    }
+
+    pub fn strings(&self) -> StringList {
+        StringList { bytes: self.value }
+    }
+
+    pub fn cells(&self) -> Cells {
+        Cells { bytes: self.value }
+    }
+
+    /// The node referred to by a phandle value.
+    pub fn as_node(&self) -> Option<Node> {
+        Node::find_by_phandle(self.as_u32()?)
+    }
+}
+
+/// The NUL separated strings of a `<stringlist>` property.
+pub struct StringList {
+    bytes: &'static [u8],
+}
+
+impl Iterator for StringList {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.bytes.iter().position(|&c| c == 0)?;
+        let string = core::str::from_utf8(&self.bytes[..len]).ok();
+        self.bytes = &self.bytes[len + 1..];
+        string
+    }
+}
+
+/// The 32-bit cells of a property value.
+pub struct Cells {
+    bytes: &'static [u8],
+}
+
+impl Cells {
+    /// Reads a number made up of `count` cells, most significant first. Only the low 64 bits are
+    /// kept.
+    pub fn read(&mut self, count: usize) -> Option<u64> {
+        (0..count).try_fold(0u64, |value, _| {
+            let cell = self.next()?;
+            Some(value.checked_shl(32).unwrap_or(0) | cell as u64)
+        })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() / 4
+    }
+}
+
+impl Iterator for Cells {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (cell, rest) = self.bytes.split_first_chunk::<4>()?;
+        self.bytes = rest;
+        Some(u32::from_be_bytes(*cell))
+    }
+}
+
+/// One `reg` entry, with the address translated into the CPU's physical address space.
+#[derive(Copy, Clone, Debug)]
+pub struct Reg {
+    pub address: usize,
+    pub size: usize,
+}
+
+pub struct RegIter {
+    cells: Cells,
+    address_cells: usize,
+    size_cells: usize,
+    bus: Node,
+}
+
+impl Iterator for RegIter {
+    type Item = Result<Reg, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.cells.read(self.address_cells)?;
+        let size = self.cells.read(self.size_cells)?;
+        Some(translate(self.bus, address).map(|address| Reg {
+            address: address as usize,
+            size: size as usize,
+        }))
+    }
+}
+
+/// Translates `address` on `bus` through the `ranges` of every bus up to the root.
+fn translate(mut bus: Node, mut address: u64) -> Result<u64, &'static str> {
+    while let Some(parent) = bus.parent() {
+        let ranges = bus
+            .property("ranges")
+            .ok_or("Bus has no ranges, address is not translatable")?;
+
+        // An empty ranges property is an identity mapping.
+        if !ranges.value.is_empty() {
+            let (child_cells, parent_cells, size_cells) = (
+                bus.address_cells(),
+                parent.address_cells(),
+                bus.size_cells(),
+            );
+            let mut cells = ranges.cells();
+
+            address = loop {
+                let (Some(child), Some(parent), Some(size)) = (
+                    cells.read(child_cells),
+                    cells.read(parent_cells),
+                    cells.read(size_cells),
+                ) else {
+                    return Err("Address outside of the bus ranges");
+                };
+                let end = child.checked_add(size).ok_or("Bus range overflows")?;
+                if (child..end).contains(&address) {
+                    break (address - child)
+                        .checked_add(parent)
+                        .ok_or("Translated address overflows")?;
+                }
+            };
+        }
+        bus = parent;
+    }
+    Ok(address)
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Node {
+    offset: usize,
+}
+
+impl Node {
+    pub fn root() -> Node {
+        Node {
+            offset: Blob::current().struct_start,
+        }
+    }
+
+    /// Looks up a node by its absolute path. Components may leave out the unit address, e.g.
+    /// `/pl011` matches `/pl011@9000000`.
+    pub fn find(path: &str) -> Option<Node> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(Node::root(), |node, component| node.child(component))
+    }
+
+    pub fn find_by_phandle(phandle: u32) -> Option<Node> {
+        Node::all().find(|node| node.phandle() == Some(phandle))
+    }
+
+    /// All nodes compatible with `compatible`, in tree order.
+    pub fn find_compatible(compatible: &str) -> impl Iterator<Item = Node> + '_ {
+        Node::all().filter(move |node| node.is_compatible(compatible))
+    }
+
+    /// Every node of the tree, depth first.
+    pub fn all() -> AllNodes {
+        let blob = Blob::current();
+        AllNodes {
+            blob,
+            offset: blob.struct_start,
+        }
+    }
+
+    /// Full name including the unit address, e.g. `pl011@9000000`. The root's name is empty.
+    pub fn name(&self) -> &'static str {
+        match Blob::current().token(self.offset) {
+            (_, Token::BeginNode(name), _) => name,
+            _ => "",
+        }
+    }
+
+    /// Name without the unit address.
+    pub fn base_name(&self) -> &'static str {
+        self.name().split('@').next().unwrap()
+    }
+
+    pub fn unit_address(&self) -> Option<&'static str> {
+        self.name().split_once('@').map(|(_, unit)| unit)
+    }
+
+    fn body(&self) -> usize {
+        Blob::current().token(self.offset).2
+    }
+
+    pub fn properties(&self) -> Properties {
+        Properties {
+            blob: Blob::current(),
+            offset: self.body(),
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<Property> {
+        self.properties().find(|property| property.name == name)
+    }
+
+    pub fn children(&self) -> Children {
+        Children {
+            blob: Blob::current(),
+            offset: self.body(),
+        }
+    }
+
+    /// The child called `name`, with or without its unit address.
+    pub fn child(&self, name: &str) -> Option<Node> {
+        self.children()
+            .find(|child| child.name() == name || child.base_name() == name)
+    }
+
+    pub fn parent(&self) -> Option<Node> {
+        let blob = Blob::current();
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut depth = 0usize;
+        let mut offset = blob.struct_start;
+
+        loop {
+            let (start, token, next) = blob.token(offset);
+            match token {
+                Token::BeginNode(_) if start == self.offset => {
+                    return depth.checked_sub(1).map(|parent| Node {
+                        offset: stack[parent],
+                    });
+                }
+                Token::BeginNode(_) => {
+                    if depth == MAX_DEPTH {
+                        return None;
+                    }
+                    stack[depth] = start;
+                    depth += 1;
+                }
+                Token::EndNode => depth = depth.checked_sub(1)?,
+                Token::Prop(_) => {}
+                Token::End => return None,
+            }
+            offset = next;
+        }
+    }
+
+    pub fn phandle(&self) -> Option<u32> {
+        self.property("phandle")
+            .or_else(|| self.property("linux,phandle"))
+            .and_then(|property| property.as_u32())
+    }
+
+    pub fn compatible(&self) -> StringList {
+        match self.property("compatible") {
+            Some(property) => property.strings(),
+            None => StringList { bytes: &[] },
+        }
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.compatible().any(|entry| entry == compatible)
+    }
+
+    /// Whether the node's `status` is absent, "okay" or "ok".
+    pub fn is_enabled(&self) -> bool {
+        self.property("status")
+            .and_then(|property| property.as_str())
+            .is_none_or(|status| status == "okay" || status == "ok")
+    }
+
+    /// `#address-cells` used by this node's children.
+    pub fn address_cells(&self) -> usize {
+        self.property("#address-cells")
+            .and_then(|property| property.as_u32())
+            .map_or(DEFAULT_ADDRESS_CELLS, |cells| cells as usize)
+    }
+
+    /// `#size-cells` used by this node's children.
+    pub fn size_cells(&self) -> usize {
+        self.property("#size-cells")
+            .and_then(|property| property.as_u32())
+            .map_or(DEFAULT_SIZE_CELLS, |cells| cells as usize)
+    }
+
+    /// The entries of `reg`, decoded with the parent's cell sizes.
+    pub fn reg(&self) -> Result<RegIter, &'static str> {
+        let bus = self.parent().ok_or("The root node has no reg")?;
+        let reg = self.property("reg").ok_or("Node has no reg")?;
+
+        let (address_cells, size_cells) = (bus.address_cells(), bus.size_cells());
+        let entry_size = (address_cells + size_cells) * 4;
+        if entry_size == 0 || reg.value.len() % entry_size != 0 {
+            return Err("Malformed reg property");
+        }
+
+        Ok(RegIter {
+            cells: reg.cells(),
+            address_cells,
+            size_cells,
+            bus,
+        })
+    }
+
+    pub fn reg_at(&self, index: usize) -> Result<Reg, &'static str> {
+        self.reg()?.nth(index).ok_or("Missing reg entry")?
+    }
+
+    /// The node's interrupt controller, from its own or its closest ancestor's `interrupt-parent`.
+    pub fn interrupt_parent(&self) -> Option<Node> {
+        let mut node = *self;
+        loop {
+            if let Some(property) = node.property("interrupt-parent") {
+                return property.as_node();
+            }
+            node = node.parent()?;
+        }
+    }
+}
+
+pub struct Properties {
+    blob: Blob,
+    offset: usize,
+}
+
+impl Iterator for Properties {
+    type Item = Property;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Properties come before any child node.
+        match self.blob.token(self.offset) {
+            (_, Token::Prop(property), next) => {
+                self.offset = next;
+                Some(property)
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct Children {
+    blob: Blob,
+    offset: usize,
+}
+
+impl Iterator for Children {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (start, token, next) = self.blob.token(self.offset);
+            match token {
+                Token::Prop(_) => self.offset = next,
+                Token::BeginNode(_) => {
+                    self.offset = self.blob.node_end(start);
+                    return Some(Node { offset: start });
+                }
+                Token::EndNode | Token::End => return None,
+            }
+        }
+    }
+}
+
+pub struct AllNodes {
+    blob: Blob,
+    offset: usize,
+}
+
+impl Iterator for AllNodes {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (start, token, next) = self.blob.token(self.offset);
+            self.offset = next;
+            match token {
+                Token::BeginNode(_) => return Some(Node { offset: start }),
+                Token::EndNode | Token::Prop(_) => {}
+                Token::End => return None,
+            }
+        }
+    }
+}
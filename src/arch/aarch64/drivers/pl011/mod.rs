@@ -3,37 +3,62 @@ mod pl011_inner;
 mod registers;
 
 use crate::{
-    arch::{drivers::devicetree, irq::Interrupt},
-    console::console,
+    arch::{
+        drivers::devicetree,
+        irq::{specifier, Interrupt},
+    },
+    console::{serial::LineSettings, tty},
     driver::interface::DeviceDriver,
+    interrupt::interface::IRQHandler,
     sync::spinlock::RawSpinlock,
 };
+use arm_gic::gicv3::{IntId, Trigger};
 use generic_once_cell::OnceCell;
 pub use pl011::PL011Uart;
+pub use pl011_inner::BaudDivisor;
 
 pub static PL011_UART: OnceCell<RawSpinlock, PL011Uart> = OnceCell::new();
 
-pub fn init(base: usize, clock_hz: u32, baud_rate: u32) {
-    #![allow(unused_must_use)]
-    PL011_UART.set(PL011Uart::new(base, clock_hz, baud_rate));
-    PL011_UART.get().unwrap().init();
+/// Sets up the PL011 at `base` and returns the divisor programmed for the requested baud rate.
+pub fn init(
+    base: usize,
+    clock_hz: u32,
+    settings: LineSettings,
+) -> Result<BaudDivisor, &'static str> {
+    PL011_UART
+        .set(PL011Uart::new(base, clock_hz, settings))
+        .map_err(|_| "PL011 already initialized")?;
+    let uart = PL011_UART.get().unwrap();
+    uart.init()?;
+    uart.baud_divisor()
 }
 
-pub fn init_irq() {
-    // Interrupt
-    let pl011_dt = devicetree::get_property("/pl011", "interrupts").unwrap();
+/// Writes `c` to the PL011 at `base` without setting it up first or taking any lock, for the early
+/// and emergency consoles. The line settings are left as they are.
+pub fn early_putc(base: usize, c: u8) {
+    use registers::{Registers, FR};
+    use tock_registers::interfaces::{Readable, Writeable};
 
-    const SPLIT_SIZE: usize = core::mem::size_of::<u32>();
-    let chunks: &[[u8; SPLIT_SIZE]] = unsafe { pl011_dt.as_chunks_unchecked() };
+    let registers = unsafe { Registers::new(base) };
+    while registers.FR.matches_all(FR::TXFF::SET) {
+        aarch64_cpu::asm::nop();
+    }
+    registers.DR.set(c as u32);
+}
+
+/// The UART interrupt as given by the first specifier of `node`.
+pub fn irq_spec(node: &devicetree::Node) -> Result<(IntId, Trigger), &'static str> {
+    specifier::parse(node, 0)
+}
 
-    Interrupt::from_raw(
-        u32::from_be_bytes(chunks[0]),
-        u32::from_be_bytes(chunks[1]),
-        u32::from_be_bytes(chunks[2]),
+pub fn init_irq(id: IntId, trigger: Trigger) {
+    Interrupt::new(
+        id,
+        trigger,
         0x00,
         |state| {
-            console().handler(|| {
-                console().echo();
+            PL011_UART.get().unwrap().handler(|| {
+                tty().receive();
             });
             true
         },
@@ -1,25 +1,44 @@
 use crate::{
-    console, driver, interrupt,
-    sync::{interface::Mutex, null_lock::NullLock},
+    arch::irq,
+    console::{self, serial::LineSettings},
+    driver, interrupt,
+    sync::{interface::Mutex, null_lock::IRQSafeNullLock},
 };
 use core::fmt;
 
 // PL011UartInner
-use super::pl011_inner::PL011UartInner;
+use super::pl011_inner::{BaudDivisor, PL011UartInner};
 use super::registers::*;
 use aarch64_cpu::registers::{Readable, Writeable};
 
 // PL011Uart is a wrapper of PL011UartInner
+//
+// Output is queued and sent from the TX interrupt, input is collected by the RX interrupt. A caller
+// with interrupts masked would wait for output that never goes out, so it is written synchronously.
 pub struct PL011Uart {
-    inner: NullLock<PL011UartInner>,
+    inner: IRQSafeNullLock<PL011UartInner>,
 }
 
 impl PL011Uart {
-    pub const fn new(base: usize, clock_hz: u32, baud_rate: u32) -> Self {
+    pub const fn new(base: usize, clock_hz: u32, settings: LineSettings) -> Self {
         Self {
-            inner: NullLock::new(PL011UartInner::new(base, clock_hz, baud_rate)),
+            inner: IRQSafeNullLock::new(PL011UartInner::new(base, clock_hz, settings)),
         }
     }
+
+    pub fn line_settings(&self) -> LineSettings {
+        self.inner.lock(|inner| inner.line_settings())
+    }
+
+    /// The divisor for the current baud rate, with the rate it really gives.
+    pub fn baud_divisor(&self) -> Result<BaudDivisor, &'static str> {
+        self.inner.lock(|inner| inner.baud_divisor())
+    }
+
+    /// Reprograms the line. On error the previous settings stay in effect.
+    pub fn set_line_settings(&self, settings: LineSettings) -> Result<BaudDivisor, &'static str> {
+        self.inner.lock(|inner| inner.set_line_settings(settings))
+    }
 }
 
 impl driver::interface::DeviceDriver for PL011Uart {
@@ -30,11 +49,28 @@ impl driver::interface::DeviceDriver for PL011Uart {
 
 impl console::interface::Write for PL011Uart {
     fn write_char(&self, c: char) {
-        self.inner.lock(|inner| inner.write_char(c))
+        let polled = irq::is_irq_masked();
+        self.inner.lock(|inner| {
+            inner.write_char(c);
+            if polled {
+                inner.flush();
+            }
+        })
     }
 
     fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
-        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+        let polled = irq::is_irq_masked();
+        self.inner.lock(|inner| {
+            let result = fmt::Write::write_fmt(inner, args);
+            if polled {
+                inner.flush();
+            }
+            result
+        })
+    }
+
+    fn try_write(&self, bytes: &[u8]) -> usize {
+        self.inner.lock(|inner| inner.try_write(bytes))
     }
 
     fn flush(&self) {
@@ -43,12 +79,16 @@ impl console::interface::Write for PL011Uart {
 }
 
 impl console::interface::Read for PL011Uart {
-    fn read_char(&self) -> char {
-        self.inner.lock(|inner| inner.read_char(false).unwrap())
+    fn read_byte(&self) -> u8 {
+        self.inner.lock(|inner| inner.read_byte(false).unwrap())
+    }
+
+    fn try_read_byte(&self) -> Option<u8> {
+        self.inner.lock(|inner| inner.read_byte(true))
     }
 
     fn clear_rx(&self) {
-        while self.inner.lock(|inner| inner.read_char(true).is_some()) {}
+        self.inner.lock(|inner| inner.clear_rx())
     }
 }
 
@@ -60,11 +100,17 @@ impl console::interface::Statistics for PL011Uart {
     fn chars_read(&self) -> usize {
         self.inner.lock(|inner| inner.chars_read)
     }
-}
 
-impl console::interface::Echo for PL011Uart {
-    fn echo(&self) {
-        self.inner.lock(|inner| inner.echo())
+    fn overrun_errors(&self) -> usize {
+        self.inner.lock(|inner| inner.overrun_errors)
+    }
+
+    fn framing_errors(&self) -> usize {
+        self.inner.lock(|inner| inner.framing_errors)
+    }
+
+    fn parity_errors(&self) -> usize {
+        self.inner.lock(|inner| inner.parity_errors)
     }
 }
 
@@ -72,12 +118,22 @@ impl console::interface::Console for PL011Uart {}
 
 impl interrupt::interface::IRQHandler for PL011Uart {
     fn handler(&self, cb: fn()) {
-        self.inner.lock(|inner| {
+        let received = self.inner.lock(|inner| {
             let pending = inner.registers.MIS.extract();
             inner.registers.ICR.write(ICR::ALL::CLEAR);
-            if pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET) {
-                cb();
+            if pending.is_set(MIS::TXMIS) {
+                inner.transmit();
+            }
+            let received = pending.matches_any(MIS::RXMIS::SET + MIS::RTMIS::SET);
+            if received {
+                inner.receive();
             }
+            received
         });
+
+        // Outside the lock, as the callback goes through the console again.
+        if received {
+            cb();
+        }
     }
 }
@@ -1,100 +1,296 @@
 use super::registers::*;
+use crate::console::ring::RingBuffer;
+use crate::console::serial::{LineSettings, Parity, StopBits};
 use crate::driver;
 use aarch64_cpu::asm;
-use core::fmt;
-use tock_registers::interfaces::{Readable, Writeable};
+use core::fmt::{self, Display};
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+const TX_BUFFER_SIZE: usize = 4096;
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Largest deviation from the requested baud rate accepted, in hundredths of a percent. Both ends
+/// together must stay within about 5% for the stop bit to be sampled correctly.
+const MAX_BAUD_ERROR: i64 = 250;
+
+/// The baud rate divisor for a requested rate, and the rate it really gives.
+#[derive(Copy, Clone, Debug)]
+pub struct BaudDivisor {
+    pub integer: u32,
+    /// In 64ths.
+    pub fraction: u32,
+    pub requested: u32,
+    pub achieved: u32,
+}
+
+impl BaudDivisor {
+    /// The divisor closest to `clock_hz / (16 * baud_rate)`.
+    pub fn new(clock_hz: u32, baud_rate: u32) -> Result<Self, &'static str> {
+        if baud_rate == 0 {
+            return Err("Invalid baud rate");
+        }
+
+        // In 64ths: 64 * clock / (16 * baud), rounded to nearest.
+        let (clock_hz, baud) = (clock_hz as u64, baud_rate as u64);
+        let divisor = (4 * clock_hz + baud / 2) / baud;
+        let integer = divisor >> 6;
+        if integer == 0 || integer > 0xffff {
+            return Err("Baud rate out of range for the UART clock");
+        }
+
+        let ret = Self {
+            integer: integer as u32,
+            fraction: (divisor & 0x3f) as u32,
+            requested: baud_rate,
+            achieved: (4 * clock_hz / divisor) as u32,
+        };
+        if ret.error().abs() > MAX_BAUD_ERROR {
+            return Err("Baud rate cannot be reached with the UART clock");
+        }
+        Ok(ret)
+    }
+
+    /// Deviation of the achieved from the requested rate, in hundredths of a percent.
+    pub fn error(&self) -> i64 {
+        (self.achieved as i64 - self.requested as i64) * 10000 / self.requested as i64
+    }
+}
+
+impl Display for BaudDivisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let error = self.error();
+        write!(
+            f,
+            "{} baud ({}{}.{:02}% off)",
+            self.achieved,
+            if error < 0 { "-" } else { "+" },
+            error.abs() / 100,
+            error.abs() % 100
+        )
+    }
+}
 
 pub(super) struct PL011UartInner {
     pub registers: Registers,
     pub chars_written: usize,
     pub chars_read: usize,
+    pub overrun_errors: usize,
+    pub framing_errors: usize,
+    pub parity_errors: usize,
+    tx: RingBuffer<u8, TX_BUFFER_SIZE>,
+    rx: RingBuffer<u8, RX_BUFFER_SIZE>,
     clock_hz: u32,
-    baud_rate: u32,
+    settings: LineSettings,
 }
 
 impl PL011UartInner {
-    pub(super) const fn new(base: usize, clock_hz: u32, baud_rate: u32) -> Self {
+    pub(super) const fn new(base: usize, clock_hz: u32, settings: LineSettings) -> Self {
         Self {
             registers: unsafe { Registers::new(base) },
             chars_written: 0,
             chars_read: 0,
+            overrun_errors: 0,
+            framing_errors: 0,
+            parity_errors: 0,
+            tx: RingBuffer::new(),
+            rx: RingBuffer::new(),
             clock_hz,
-            baud_rate,
+            settings,
         }
     }
 
-    pub(super) fn flush(&self) {
+    pub(super) fn line_settings(&self) -> LineSettings {
+        self.settings
+    }
+
+    pub(super) fn baud_divisor(&self) -> Result<BaudDivisor, &'static str> {
+        BaudDivisor::new(self.clock_hz, self.settings.baud_rate)
+    }
+
+    /// Programs `settings`, waiting for the transmitter to go idle as the UART is disabled meanwhile.
+    fn program_line(&self, settings: &LineSettings) -> Result<BaudDivisor, &'static str> {
+        let divisor = BaudDivisor::new(self.clock_hz, settings.baud_rate)?;
+
         while self.registers.FR.matches_all(FR::BUSY::SET) {
             asm::nop();
         }
+        self.registers.CR.set(0);
+
+        self.registers
+            .IBRD
+            .write(IBRD::BAUD_DIVINT.val(divisor.integer));
+        self.registers
+            .FBRD
+            .write(FBRD::BAUD_DIVFRAC.val(divisor.fraction));
+
+        // Also latches the divisor.
+        let wlen = match settings.data_bits {
+            5 => LCR_H::WLEN::FiveBit,
+            6 => LCR_H::WLEN::SixBit,
+            7 => LCR_H::WLEN::SevenBit,
+            _ => LCR_H::WLEN::EightBit,
+        };
+        let parity = match settings.parity {
+            Parity::None => LCR_H::PEN::Disabled,
+            Parity::Odd => LCR_H::PEN::Enabled + LCR_H::EPS::OddParity,
+            Parity::Even => LCR_H::PEN::Enabled + LCR_H::EPS::EvenParity,
+        };
+        let stop_bits = match settings.stop_bits {
+            StopBits::One => LCR_H::STP2::OneStopBit,
+            StopBits::Two => LCR_H::STP2::TwoStopBits,
+        };
+        self.registers
+            .LCR_H
+            .write(wlen + parity + stop_bits + LCR_H::FEN::FifosEnabled);
+
+        // Enable UART, RX, TX
+        let flow_control = match settings.flow_control {
+            true => CR::RTSEN::Enabled + CR::CTSEN::Enabled,
+            false => CR::RTSEN::Disabled + CR::CTSEN::Disabled,
+        };
+        self.registers
+            .CR
+            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled + flow_control);
+
+        Ok(divisor)
     }
 
-    pub(super) fn write_char(&mut self, c: char) {
+    /// Switches to `settings`. Output queued so far goes out with the old settings.
+    pub(super) fn set_line_settings(
+        &mut self,
+        settings: LineSettings,
+    ) -> Result<BaudDivisor, &'static str> {
         self.flush();
-        self.registers.DR.set(c as u32);
-        self.chars_written += 1;
+        let divisor = self.program_line(&settings)?;
+        self.settings = settings;
+        Ok(divisor)
+    }
+
+    /// Moves queued bytes into the TX FIFO until either runs out. The TX interrupt is left enabled
+    /// while bytes remain, so the handler picks up where this stopped.
+    pub(super) fn transmit(&mut self) {
+        while !self.registers.FR.matches_all(FR::TXFF::SET) {
+            match self.tx.pop() {
+                Some(byte) => self.registers.DR.set(byte as u32),
+                None => break,
+            }
+        }
+
+        let pending = !self.tx.is_empty();
+        self.registers.IMSC.modify(if pending {
+            IMSC::TXIM::Enabled
+        } else {
+            IMSC::TXIM::Disabled
+        });
+    }
+
+    /// Moves the RX FIFO into the receive buffer, counting line errors. Characters with framing or
+    /// parity errors, or received as part of a break, are dropped.
+    pub(super) fn receive(&mut self) {
+        while !self.registers.FR.matches_all(FR::RXFE::SET) {
+            let data = self.registers.DR.extract();
+
+            // The character is fine; the one after it was lost.
+            if data.is_set(DR::OE) {
+                self.overrun_errors += 1;
+            }
+            if data.is_set(DR::FE) {
+                self.framing_errors += 1;
+            }
+            if data.is_set(DR::PE) {
+                self.parity_errors += 1;
+            }
+            if data.matches_any(DR::FE::SET + DR::PE::SET + DR::BE::SET) {
+                continue;
+            }
+
+            // Nobody read the buffer in time either.
+            if !self.rx.push(data.read(DR::DATA) as u8) {
+                self.overrun_errors += 1;
+            }
+        }
     }
 
-    pub(super) fn read_char(&mut self, nonblocking: bool) -> Option<char> {
-        if nonblocking && self.registers.FR.matches_all(FR::RXFE::SET) {
-            return None;
+    /// Writes out everything queued, waiting for the FIFO. Used where the TX interrupt cannot be
+    /// taken.
+    pub(super) fn flush(&mut self) {
+        while let Some(byte) = self.tx.pop() {
+            while self.registers.FR.matches_all(FR::TXFF::SET) {
+                asm::nop();
+            }
+            self.registers.DR.set(byte as u32);
         }
+        self.registers.IMSC.modify(IMSC::TXIM::Disabled);
 
-        while self.registers.FR.matches_all(FR::RXFE::SET) {
+        while self.registers.FR.matches_all(FR::BUSY::SET) {
             asm::nop();
         }
+    }
 
-        let mut c = self.registers.DR.get() as u8 as char;
-        if c == '\r' {
-            c = '\n';
+    /// Queues `c`, waiting for space if the buffer is full.
+    pub(super) fn write_char(&mut self, c: char) {
+        let mut buf = [0u8; 4];
+        for &byte in c.encode_utf8(&mut buf).as_bytes() {
+            while !self.tx.push(byte) {
+                // Make room by feeding the FIFO directly; the caller may have interrupts masked.
+                self.transmit();
+                asm::nop();
+            }
+            self.chars_written += 1;
         }
+        self.transmit();
+    }
 
-        self.chars_read += 1;
+    /// Queues as much of `bytes` as fits and returns how many bytes that was.
+    pub(super) fn try_write(&mut self, bytes: &[u8]) -> usize {
+        let written = bytes.iter().take_while(|&&byte| self.tx.push(byte)).count();
+        self.chars_written += written;
+        self.transmit();
+        written
+    }
 
-        Some(c)
+    pub(super) fn read_byte(&mut self, nonblocking: bool) -> Option<u8> {
+        let byte = loop {
+            // Also picks up characters while the RX interrupt cannot be taken.
+            self.receive();
+            match self.rx.pop() {
+                Some(byte) => break byte,
+                None if nonblocking => return None,
+                None => asm::nop(),
+            }
+        };
+
+        self.chars_read += 1;
+        Some(byte)
     }
 
-    pub(super) fn echo(&mut self) {
-        while let Some(c) = self.read_char(true) {
-            self.write_char(c)
-        }
+    pub(super) fn clear_rx(&mut self) {
+        self.receive();
+        self.rx.clear();
     }
 }
 
 impl driver::interface::DeviceDriver for PL011UartInner {
     fn init(&self) -> Result<(), &'static str> {
-        self.flush();
+        while self.registers.FR.matches_all(FR::BUSY::SET) {
+            asm::nop();
+        }
 
         // Clear
         self.registers.CR.set(0);
         self.registers.ICR.write(ICR::ALL::CLEAR);
 
-        // Set baud rate: BRD = clock / (16 * baud)
-        let brd_x64 = (4 * self.clock_hz) / self.baud_rate;
-        let ibrd = brd_x64 / 64;
-        let fbrd = brd_x64 % 64;
-        self.registers.IBRD.write(IBRD::BAUD_DIVINT.val(ibrd));
-        self.registers.FBRD.write(FBRD::BAUD_DIVFRAC.val(fbrd));
-
-        // Set Data Frame
+        // Set RX FIFO fill level at 1/8, and refill the TX FIFO once it drains to 1/8.
         self.registers
-            .LCR_H
-            .write(LCR_H::WLEN::EightBit + LCR_H::FEN::FifosEnabled);
-
-        // Set RX FIFO fill level at 1/8.
-        self.registers.IFLS.write(IFLS::RXIFLSEL::OneEigth);
+            .IFLS
+            .write(IFLS::RXIFLSEL::OneEigth + IFLS::TXIFLSEL::OneEigth);
 
-        // Enable RX IRQ + RX timeout IRQ.
+        // Enable RX IRQ + RX timeout IRQ. The TX IRQ is enabled while there is output queued.
         self.registers
             .IMSC
             .write(IMSC::RXIM::Enabled + IMSC::RTIM::Enabled);
 
-        // Set Control Register
-        // Enable UART, RX, TX
-        self.registers
-            .CR
-            .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
-
+        self.program_line(&self.settings)?;
         Ok(())
     }
 }
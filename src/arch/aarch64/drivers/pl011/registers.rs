@@ -9,6 +9,15 @@ use tock_registers::{
 register_bitfields! {
     u32,
 
+    /// Data Register. Received characters come with their error flags.
+    pub DR [
+        OE OFFSET(11) NUMBITS(1) [],
+        BE OFFSET(10) NUMBITS(1) [],
+        PE OFFSET(9) NUMBITS(1) [],
+        FE OFFSET(8) NUMBITS(1) [],
+        DATA OFFSET(0) NUMBITS(8) []
+    ],
+
     /// Flag Register.
     pub FR [
         TXFE OFFSET(7) NUMBITS(1) [],
@@ -39,11 +48,31 @@ register_bitfields! {
         FEN  OFFSET(4) NUMBITS(1) [
             FifosDisabled = 0,
             FifosEnabled = 1
+        ],
+        STP2 OFFSET(3) NUMBITS(1) [
+            OneStopBit = 0,
+            TwoStopBits = 1
+        ],
+        EPS OFFSET(2) NUMBITS(1) [
+            OddParity = 0,
+            EvenParity = 1
+        ],
+        PEN OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
         ]
     ],
 
     /// Control Register.
     pub CR [
+        CTSEN OFFSET(15) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+        RTSEN OFFSET(14) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
         RXE OFFSET(9) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
@@ -60,7 +89,14 @@ register_bitfields! {
 
     /// Interrupt FIFO Level Select Register.
     pub IFLS [
-        RXIFLSEL OFFSET(3) NUMBITS(5) [
+        RXIFLSEL OFFSET(3) NUMBITS(3) [
+            OneEigth = 0b000,
+            OneQuarter = 0b001,
+            OneHalf = 0b010,
+            ThreeQuarters = 0b011,
+            SevenEights = 0b100
+        ],
+        TXIFLSEL OFFSET(0) NUMBITS(3) [
             OneEigth = 0b000,
             OneQuarter = 0b001,
             OneHalf = 0b010,
@@ -75,6 +111,10 @@ register_bitfields! {
             Disabled = 0,
             Enabled = 1
         ],
+        TXIM OFFSET(5) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
         RXIM OFFSET(4) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
@@ -84,6 +124,7 @@ register_bitfields! {
     /// Masked Interrupt Status Register.
     pub MIS [
         RTMIS OFFSET(6) NUMBITS(1) [],
+        TXMIS OFFSET(5) NUMBITS(1) [],
         RXMIS OFFSET(4) NUMBITS(1) []
     ],
 
@@ -96,7 +137,7 @@ register_bitfields! {
 register_structs! {
     #[allow(non_snake_case)]
     pub RegisterBlock {
-        (0x00 => pub DR: ReadWrite<u32>),
+        (0x00 => pub DR: ReadWrite<u32, DR::Register>),
         (0x04 => _reserved1),
         (0x18 => pub FR: ReadOnly<u32, FR::Register>),
         (0x1c => _reserved2),
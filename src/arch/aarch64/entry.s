@@ -1,6 +1,9 @@
 // Adapted from https://github.com/rust-embedded/rust-raspberrypi-OS-tutorials/blob/master/02_runtime_init/src/_arch/aarch64/cpu/boot.s
 
 .equ _core_id_mask, 0xff
+.equ R_AARCH64_RELATIVE, 1027
+// Passed in x1 when KASLR restarts the kernel at its new address, see kaslr::REENTRY_MAGIC.
+.equ KASLR_REENTRY_MAGIC, 0x00524c53414b
 
 .macro ADR_REL register, symbol
   adrp \register, \symbol
@@ -9,11 +12,65 @@
 
 .section .text._start
 
+// arm64 Image header (Documentation/arch/arm64/booting.rst in Linux). It lets boot loaders such as
+// U-Boot's `booti` or QEMU load the flat binary produced by `make image`. For ELF boots it is just
+// a branch over the header.
 _start:
-  // Only proceed if the core executes in EL2. Park it otherwise.
+  b .L_primary_entry            // code0
+  .long 0                       // code1
+  .quad __image_text_offset_    // text_offset: image load offset from a 2 MiB aligned base
+  .quad __image_size_           // image_size: effective image size, including bss and stack
+  .quad 0xe                     // flags: little endian, 64K pages, placement anywhere
+  .quad 0                       // res2
+  .quad 0                       // res3
+  .quad 0                       // res4
+  .ascii "ARM\x64"              // magic
+  .long 0                       // res5
+
+.L_primary_entry:
+  // The code below runs before the kernel knows where it was loaded and must stay position
+  // independent, i.e. only use PC-relative addressing (ADR_REL) until it reaches Rust.
+
+  // Per the arm64 boot protocol x0 holds the physical address of the device tree blob. Keep it in
+  // a callee-saved register until it is handed to Rust.
+  mov x19, x0
+  // x1 is zero unless KASLR restarts the kernel.
+  mov x21, x1
+
+  // The kernel is linked as a position independent executable. Apply its R_AARCH64_RELATIVE
+  // relocations for the address it was actually loaded at before any Rust code runs. The addends
+  // are only kept in .rela.dyn, so this is required even if the image sits at its link address.
+  ADR_REL x1, _start
+  ldr x2, =__image_link_addr_
+  sub x20, x1, x2
+  ADR_REL x3, __rela_start_
+  ADR_REL x4, __rela_end_
+
+.L_relocate_loop:
+  cmp x3, x4
+  b.hs .L_relocate_done
+  ldp x5, x6, [x3], #16   // r_offset, r_info
+  ldr x7, [x3], #8        // r_addend
+  cmp w6, #R_AARCH64_RELATIVE
+  b.ne .L_parking_loop    // Anything else means the kernel was built wrongly.
+  add x7, x7, x20
+  str x7, [x5, x20]
+  b .L_relocate_loop
+
+.L_relocate_done:
+
+  // Only proceed if the core executes in EL2, or in EL1 when KASLR restarts the kernel. Park it
+  // otherwise.
   mrs x1, CurrentEL
   cmp x1, #0x8
+  b.eq .L_el_checked
+  cmp x1, #0x4
   b.ne .L_parking_loop
+  ldr x2, =KASLR_REENTRY_MAGIC
+  cmp x21, x2
+  b.ne .L_parking_loop
+
+.L_el_checked:
 
   ADR_REL x0, __bss_start_
   ADR_REL x1, __bss_end_
@@ -45,7 +102,10 @@ _start:
   ADR_REL x0, __boot_core_stack_end_
 	mov		sp, x0
 
-	// Jump to Rust code.
+	// Jump to Rust code: _start_cosmos(stack_end, dtb_addr, load_offset, boot_x1)
+	mov		x1, x19
+	mov		x2, x20
+	mov		x3, x21
 	b	_start_cosmos
 
 	// Infinitely wait for events (aka "park the core").
@@ -13,22 +13,34 @@ use arm_gic::gicv3::GicV3;
 // This happens when `SPSel` register holds the value 0
 #[no_mangle]
 extern "C" fn handle_el1t_sync(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el1t_sync Called!");
+    fatal("handle_el1t_sync", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el1t_irq(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el1t_irq Called!");
+    fatal("handle_el1t_irq", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el1t_fiq(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el1t_fiq Called!");
+    fatal("handle_el1t_fiq", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el1t_err(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el1t_err Called!");
+    fatal("handle_el1t_err", state)
+}
+
+/// Reports an exception the kernel cannot recover from. The panic handler prints it on the
+/// emergency console.
+fn fatal(handler: &str, state: &ExceptionState) -> ! {
+    panic!(
+        "{} Called!\nESR_EL1: {:#018x}\nFAR_EL1: {:#018x}\n{}",
+        handler,
+        ESR_EL1.get(),
+        FAR_EL1.get(),
+        state
+    );
 }
 
 fn revive_from_fault(state: &mut ExceptionState) {
@@ -66,7 +78,7 @@ extern "C" fn handle_el1h_sync(state: &mut ExceptionState) -> *mut usize {
     //     return core::ptr::null_mut();
     // }
 
-    panic!("handle_el1h_sync Called!");
+    fatal("handle_el1h_sync", state);
 }
 
 #[no_mangle]
@@ -81,48 +93,48 @@ extern "C" fn handle_el1h_fiq(state: ExceptionState) -> *mut usize {
 
 #[no_mangle]
 extern "C" fn handle_el1h_err(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el1h_err Called!");
+    fatal("handle_el1h_err", state)
 }
 
 /* Lower EL using AArch64 */
 // Exception is taken from EL0 while running in 64-bit mode
 #[no_mangle]
 extern "C" fn handle_el0_sync64(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_sync64 Called!");
+    fatal("handle_el0_sync64", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_irq64(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_irq64 Called!");
+    fatal("handle_el0_irq64", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_fiq64(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_fiq64 Called!");
+    fatal("handle_el0_fiq64", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_err64(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_err64 Called!");
+    fatal("handle_el0_err64", state)
 }
 
 /* Lower EL using AArch32 */
 #[no_mangle]
 extern "C" fn handle_el0_sync32(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_sync32 Called!");
+    fatal("handle_el0_sync32", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_irq32(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_irq32 Called!");
+    fatal("handle_el0_irq32", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_fiq32(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_fiq32 Called!");
+    fatal("handle_el0_fiq32", state)
 }
 
 #[no_mangle]
 extern "C" fn handle_el0_err32(state: &ExceptionState) -> *mut usize {
-    panic!("handle_el0_err32 Called!");
+    fatal("handle_el0_err32", state)
 }
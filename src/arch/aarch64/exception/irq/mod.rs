@@ -1,4 +1,5 @@
 pub mod irq_type;
+pub mod specifier;
 
 use self::irq_type::InterruptType;
 use super::state::ExceptionState;
@@ -23,6 +24,7 @@ where
     F: FnOnce() -> R,
 {
     let daif = DAIF.get();
+    DAIF.modify(DAIF::I::Masked);
     let ret = f();
     DAIF.set(daif);
     ret
@@ -33,9 +35,26 @@ pub fn init_gic(gicd: *mut u64, gicr: *mut u64) -> Result<(), GicV3> {
     GicV3::set_priority_mask(0xff);
     gic.setup();
 
-    unsafe { GIC.set(gic) }
+    unsafe { GIC.set(gic)? };
+
+    Interrupt::new(
+        IntId::sgi(STOP_SGI),
+        Trigger::Edge,
+        0x00,
+        |_| crate::console::emergency::stop(),
+        "CPU stop",
+    )
+    .register();
+    Ok(())
+}
+
+pub fn is_initialized() -> bool {
+    unsafe { (*core::ptr::addr_of!(GIC)).get().is_some() }
 }
 
+/// Sent to the other cores to stop them when the kernel panics.
+const STOP_SGI: u32 = 15;
+
 const SGI_START: u32 = 0;
 const SGI_END: u32 = 15;
 
@@ -55,32 +74,14 @@ pub struct Interrupt {
 }
 
 impl Interrupt {
-    pub fn from_raw(
-        irq_type: u32,
-        id: u32,
-        trigger: u32,
+    pub fn new(
+        id: IntId,
+        trigger: Trigger,
         prio: u8,
         handler: Handler,
         name: &'static str,
     ) -> Self {
-        let id = u32::from(match irq_type {
-            0 => IntId::spi(id),
-            1 => IntId::ppi(id),
-            _ => IntId::sgi(id),
-        });
-
-        Interrupt::new(id, trigger, prio, handler, name)
-    }
-
-    pub fn new(id: u32, trigger: u32, prio: u8, handler: Handler, name: &'static str) -> Self {
-        let trigger = match trigger {
-            4 | 8 => Trigger::Level,
-            2 | 1 => Trigger::Edge,
-            // SGI is always edge triggered
-            _ => Trigger::Edge,
-            // _ => panic!("Invalid interrupt trigger type!"),
-        };
-
+        let id = u32::from(id);
         assert!(id >= SGI_START && id <= SPI_END);
 
         let ret = Self {
@@ -196,6 +197,18 @@ pub fn send_sgi(id: u32) {
     );
 }
 
+/// Stops every core but the calling one, if the GIC is up to deliver the request.
+pub fn stop_other_cpus() {
+    if is_initialized() {
+        GicV3::send_sgi(IntId::sgi(STOP_SGI), SgiTarget::All);
+    }
+}
+
+/// Whether IRQs are masked on the calling core.
+pub fn is_irq_masked() -> bool {
+    DAIF.matches_all(DAIF::I::Masked)
+}
+
 pub fn irq_enable() {
     DAIF.modify(DAIF::I::Unmasked);
 }
@@ -0,0 +1,223 @@
+// Device tree interrupt specifiers
+//
+// A device's `interrupts` cells are interpreted by its interrupt parent, which is either an
+// interrupt controller or a nexus node that translates them with `interrupt-map` into a specifier
+// of its own parent. The walk ends at the GIC, whose three-cell specifiers are
+// <type number flags> (see the arm,gic-v3 binding).
+
+use crate::arch::drivers::devicetree::{Cells, Node};
+use arm_gic::gicv3::{IntId, Trigger};
+
+/// Longest unit address or specifier we handle, in cells.
+const MAX_CELLS: usize = 4;
+
+/// Bounds the `interrupt-map` walk, in case a map points back at itself.
+const MAX_DEPTH: usize = 8;
+
+const GIC_SPI: u32 = 0;
+const GIC_PPI: u32 = 1;
+
+const IRQ_TYPE_EDGE_RISING: u32 = 1;
+const IRQ_TYPE_EDGE_FALLING: u32 = 2;
+const IRQ_TYPE_LEVEL_HIGH: u32 = 4;
+const IRQ_TYPE_LEVEL_LOW: u32 = 8;
+const IRQ_TYPE_SENSE_MASK: u32 = 0xf;
+
+/// A unit address or interrupt specifier.
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+struct CellList {
+    cells: [u32; MAX_CELLS],
+    len: usize,
+}
+
+impl CellList {
+    fn read(cells: &mut Cells, len: usize) -> Result<Self, &'static str> {
+        if len > MAX_CELLS {
+            return Err("Interrupt specifier too long");
+        }
+        let mut list = CellList {
+            len,
+            ..Default::default()
+        };
+        for cell in &mut list.cells[..len] {
+            *cell = cells.next().ok_or("Truncated interrupt specifier")?;
+        }
+        Ok(list)
+    }
+
+    fn masked(&self, mask: &CellList) -> Self {
+        let mut masked = *self;
+        for (cell, mask) in masked.cells[..self.len].iter_mut().zip(mask.cells) {
+            *cell &= mask;
+        }
+        masked
+    }
+}
+
+fn interrupt_cells(controller: &Node) -> Result<usize, &'static str> {
+    controller
+        .property("#interrupt-cells")
+        .and_then(|property| property.as_u32())
+        .map(|cells| cells as usize)
+        .ok_or("Interrupt parent has no #interrupt-cells")
+}
+
+/// `#address-cells` of an interrupt parent, which unlike for buses defaults to 0.
+fn map_address_cells(node: &Node) -> usize {
+    node.property("#address-cells")
+        .and_then(|property| property.as_u32())
+        .map_or(0, |cells| cells as usize)
+}
+
+/// The untranslated first address of `node`'s `reg`, used to look it up in an `interrupt-map`.
+fn unit_address(node: &Node, len: usize) -> Result<CellList, &'static str> {
+    match node.property("reg") {
+        Some(reg) => CellList::read(&mut reg.cells(), len),
+        None => Ok(CellList {
+            len,
+            ..Default::default()
+        }),
+    }
+}
+
+/// Looks up `(address, specifier)` in `nexus`'s `interrupt-map`, returning the parent and the
+/// parent's unit address and specifier.
+fn map(
+    nexus: &Node,
+    address: CellList,
+    specifier: CellList,
+) -> Result<(Node, CellList, CellList), &'static str> {
+    let mask = match nexus.property("interrupt-map-mask") {
+        Some(mask) => {
+            let mut mask = mask.cells();
+            (
+                CellList::read(&mut mask, address.len)?,
+                CellList::read(&mut mask, specifier.len)?,
+            )
+        }
+        None => {
+            let ones = CellList {
+                cells: [!0; MAX_CELLS],
+                len: MAX_CELLS,
+            };
+            (ones, ones)
+        }
+    };
+    let key = (address.masked(&mask.0), specifier.masked(&mask.1));
+
+    let mut entries = nexus.property("interrupt-map").unwrap().cells();
+    while entries.remaining() > 0 {
+        let child_address = CellList::read(&mut entries, address.len)?;
+        let child_specifier = CellList::read(&mut entries, specifier.len)?;
+        let phandle = entries.next().ok_or("Truncated interrupt-map")?;
+        let parent =
+            Node::find_by_phandle(phandle).ok_or("interrupt-map refers to unknown node")?;
+        let parent_address = CellList::read(&mut entries, map_address_cells(&parent))?;
+        let parent_specifier = CellList::read(&mut entries, interrupt_cells(&parent)?)?;
+
+        if (child_address, child_specifier) == key {
+            return Ok((parent, parent_address, parent_specifier));
+        }
+    }
+    Err("No interrupt-map entry for interrupt")
+}
+
+/// Decodes a GIC specifier.
+fn gic_interrupt(specifier: CellList) -> Result<(IntId, Trigger), &'static str> {
+    if specifier.len < 3 {
+        return Err("GIC interrupt specifier needs 3 cells");
+    }
+    let [kind, number, flags, ..] = specifier.cells;
+
+    let id = match kind {
+        GIC_SPI if number < 988 => IntId::spi(number),
+        GIC_PPI if number < 16 => IntId::ppi(number),
+        GIC_SPI | GIC_PPI => return Err("Interrupt number out of range"),
+        _ => return Err("Unsupported GIC interrupt type"),
+    };
+
+    let trigger = match flags & IRQ_TYPE_SENSE_MASK {
+        IRQ_TYPE_EDGE_RISING | IRQ_TYPE_EDGE_FALLING => Trigger::Edge,
+        IRQ_TYPE_LEVEL_HIGH | IRQ_TYPE_LEVEL_LOW => Trigger::Level,
+        _ => return Err("Invalid interrupt trigger type"),
+    };
+
+    Ok((id, trigger))
+}
+
+/// Follows `specifier`, as given by `node` to `controller`, up to the GIC.
+fn resolve(
+    node: &Node,
+    mut controller: Node,
+    mut specifier: CellList,
+) -> Result<(IntId, Trigger), &'static str> {
+    // The device's own unit address is only needed if its parent is a nexus.
+    let mut address = None;
+
+    for _ in 0..MAX_DEPTH {
+        if controller.property("interrupt-map").is_some() {
+            let child_address = match address {
+                Some(address) => address,
+                None => unit_address(node, controller.address_cells())?,
+            };
+            let parent_address;
+            (controller, parent_address, specifier) = map(&controller, child_address, specifier)?;
+            address = Some(parent_address);
+        } else if controller.property("interrupt-controller").is_some() {
+            if !controller.is_compatible("arm,gic-v3") {
+                return Err("Interrupt controller is not a GICv3");
+            }
+            return gic_interrupt(specifier);
+        } else {
+            return Err("Interrupt parent is neither a controller nor a nexus");
+        }
+    }
+    Err("interrupt-map nested too deeply")
+}
+
+/// The `index`th interrupt of `node`, from `interrupts-extended` or `interrupts`.
+pub fn parse(node: &Node, index: usize) -> Result<(IntId, Trigger), &'static str> {
+    if let Some(extended) = node.property("interrupts-extended") {
+        // <phandle specifier...> pairs, each specifier sized by its own controller.
+        let mut cells = extended.cells();
+        let mut i = 0;
+        loop {
+            let phandle = cells.next().ok_or("No such interrupt")?;
+            let controller = Node::find_by_phandle(phandle).ok_or("Unknown interrupt parent")?;
+            let specifier = CellList::read(&mut cells, interrupt_cells(&controller)?)?;
+            if i == index {
+                return resolve(node, controller, specifier);
+            }
+            i += 1;
+        }
+    }
+
+    let interrupts = node
+        .property("interrupts")
+        .ok_or("Node has no interrupts")?;
+    let controller = node
+        .interrupt_parent()
+        .ok_or("Node has no interrupt parent")?;
+    let count = interrupt_cells(&controller)?;
+    if count == 0 || interrupts.value.len() % (count * 4) != 0 {
+        return Err("Malformed interrupts property");
+    }
+
+    let mut cells = interrupts.cells();
+    cells.by_ref().take(index * count).for_each(drop);
+    if cells.remaining() < count {
+        return Err("No such interrupt");
+    }
+    resolve(node, controller, CellList::read(&mut cells, count)?)
+}
+
+/// The interrupt listed as `name` in `node`'s `interrupt-names`.
+pub fn parse_by_name(node: &Node, name: &str) -> Result<(IntId, Trigger), &'static str> {
+    let index = node
+        .property("interrupt-names")
+        .ok_or("Node has no interrupt-names")?
+        .strings()
+        .position(|entry| entry == name)
+        .ok_or("No interrupt by that name")?;
+    parse(node, index)
+}
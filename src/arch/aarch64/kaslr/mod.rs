@@ -0,0 +1,169 @@
+// Kernel address space layout randomization
+//
+// The kernel is identity mapped, so its virtual base is the physical address it runs at. Early in
+// boot it picks a random 2 MiB aligned slot in RAM, copies itself there and restarts at the copy's
+// entry point, where entry.s applies the relocations for the new address.
+
+use crate::arch::drivers::devicetree;
+use crate::arch::memory::{get_ramrange, maintenance};
+use crate::bsp::memory::symbols;
+use crate::memory::types::Address;
+use aarch64_cpu::registers::*;
+use core::arch::asm;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Passed in x1 when the kernel restarts itself at the new address. Must match entry.s.
+pub const REENTRY_MAGIC: u64 = 0x0052_4c53_414b; // "KASLR"
+
+const SLOT_ALIGN: usize = 2 * 1024 * 1024;
+/// Ranges the kernel must not be copied over: itself, the device tree, the initrd and the reserved
+/// memory.
+const MAX_AVOID: usize = 16;
+
+crate::param!(NOKASLR: bool = "nokaslr", false);
+
+#[link_section = ".data.ro_after_init"]
+static LOAD_OFFSET: AtomicUsize = AtomicUsize::new(0);
+#[link_section = ".data.ro_after_init"]
+static RANDOMIZED: AtomicBool = AtomicBool::new(false);
+
+/// Records where entry.s found the kernel. Called from `_start_cosmos`.
+pub fn early_init(load_offset: usize, reentered: bool) {
+    LOAD_OFFSET.store(load_offset, Ordering::Relaxed);
+    RANDOMIZED.store(reentered, Ordering::Relaxed);
+}
+
+/// Difference between the address the kernel runs at and its link address. Subtract it from
+/// addresses in backtraces before looking them up in the ELF file.
+pub fn load_offset() -> usize {
+    LOAD_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Whether the kernel was moved to a random address.
+pub fn is_randomized() -> bool {
+    RANDOMIZED.load(Ordering::Relaxed)
+}
+
+fn rndr() -> Option<u64> {
+    if ID_AA64ISAR0_EL1.read(ID_AA64ISAR0_EL1::RNDR) == 0 {
+        return None;
+    }
+
+    // RNDR may fail transiently, which it reports by setting NZCV.Z.
+    for _ in 0..8 {
+        let (value, ok): (u64, u64);
+        unsafe {
+            asm!(
+                "mrs {value}, s3_3_c2_c4_0",
+                "cset {ok}, ne",
+                value = out(reg) value,
+                ok = out(reg) ok,
+                options(nomem, nostack)
+            )
+        };
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn seed() -> Option<u64> {
+    let chosen = devicetree::Node::find("/chosen");
+    let property = |name| chosen.and_then(|chosen| chosen.property(name));
+
+    if let Some(seed) = property("kaslr-seed")
+        .and_then(|seed| seed.as_usize())
+        .filter(|&seed| seed != 0)
+    {
+        return Some(seed as u64);
+    }
+
+    if let Some(rng_seed) = property("rng-seed") {
+        let seed = rng_seed.value.chunks(8).fold(0u64, |acc, chunk| {
+            let mut buf = [0u8; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            acc.rotate_left(17) ^ u64::from_be_bytes(buf)
+        });
+        if seed != 0 {
+            return Some(seed);
+        }
+    }
+
+    rndr()
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// Moves the kernel to a random slot in RAM and restarts it there.
+///
+/// Returns without doing anything if the kernel was already moved, `nokaslr` is given, or no seed
+/// or free slot is available.
+///
+/// # Safety
+///
+/// Must run with the MMU off, before anything but the device tree and the command line is set up.
+pub unsafe fn randomize() {
+    if is_randomized() || NOKASLR.get() {
+        return;
+    }
+    let Some(seed) = seed() else {
+        return;
+    };
+
+    let image = symbols::image_range();
+    let image = image.start.value()..image.end.value();
+    let footprint = image.end - image.start;
+
+    let (ram_start, ram_size) = get_ramrange();
+    let ram = ram_start as usize..ram_start as usize + ram_size.0;
+
+    // Firmware owns the reserved ranges. Staying put is better than overwriting one that did not
+    // fit in `avoid`.
+    let mut avoid: [Range<usize>; MAX_AVOID] = core::array::from_fn(|_| 0..0);
+    let ranges = [
+        image.clone(),
+        devicetree::blob_range(),
+        devicetree::initrd_range().unwrap_or(0..0),
+    ];
+    let mut count = 0;
+    for range in ranges.into_iter().chain(devicetree::reserved_memory()) {
+        let Some(slot) = avoid.get_mut(count) else {
+            return;
+        };
+        *slot = range;
+        count += 1;
+    }
+    let avoid = &avoid[..count];
+    let slots = || {
+        (ram.start.next_multiple_of(SLOT_ALIGN)..=ram.end.saturating_sub(footprint))
+            .step_by(SLOT_ALIGN)
+            .filter(|&base| !avoid.iter().any(|r| overlaps(&(base..base + footprint), r)))
+    };
+
+    let count = slots().count();
+    if count == 0 {
+        return;
+    }
+    let base = slots().nth((seed % count as u64) as usize).unwrap();
+
+    let loaded = symbols::loaded_range();
+    let src = loaded.start.value();
+    let len = loaded.end.value() - src;
+    let dst = base + (src - image.start);
+
+    core::ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, len);
+    maintenance::icache_sync_range(Address::new(dst)..Address::new(dst + len));
+
+    // The copy starts with the Image header, i.e. at _start.
+    asm!(
+        "br {entry}",
+        entry = in(reg) dst,
+        in("x0") devicetree::blob_range().start,
+        in("x1") REENTRY_MAGIC,
+        options(noreturn)
+    );
+}
@@ -0,0 +1,230 @@
+// TLB and cache maintenance
+//
+// Every operation comes in a local flavour, which only affects the executing core (`nsh`), and an
+// inner-shareable flavour (`_is`) which is broadcast to all cores in the inner shareable domain.
+
+use crate::bsp::memory::KernelGranule;
+use crate::memory::types::*;
+use aarch64_cpu::asm::barrier;
+use core::arch::asm;
+use core::ops::Range;
+
+/// Ranges spanning more pages than this are flushed as a whole instead of page by page.
+const TLB_FLUSH_PAGE_THRESHOLD: usize = 64;
+
+pub type Asid = u16;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Scope {
+    Local,
+    InnerShareable,
+}
+
+/// Operand of `TLBI *VA*`: VA[55:12] in bits [43:0] and the ASID in bits [63:48].
+#[inline(always)]
+fn tlbi_va_operand(va: usize, asid: Asid) -> u64 {
+    ((va as u64 >> 12) & ((1 << 44) - 1)) | ((asid as u64) << 48)
+}
+
+#[inline(always)]
+fn dsb_after_tlbi(scope: Scope) {
+    match scope {
+        Scope::Local => barrier::dsb(barrier::NSH),
+        Scope::InnerShareable => barrier::dsb(barrier::ISH),
+    }
+    barrier::isb(barrier::SY);
+}
+
+fn tlb_flush_all_scoped(scope: Scope) {
+    // Make preceding page table updates visible to the table walker.
+    barrier::dsb(barrier::ISHST);
+    unsafe {
+        match scope {
+            Scope::Local => asm!("tlbi vmalle1", options(nostack)),
+            Scope::InnerShareable => asm!("tlbi vmalle1is", options(nostack)),
+        }
+    }
+    dsb_after_tlbi(scope);
+}
+
+fn tlb_flush_asid_scoped(asid: Asid, scope: Scope) {
+    let operand = (asid as u64) << 48;
+
+    barrier::dsb(barrier::ISHST);
+    unsafe {
+        match scope {
+            Scope::Local => asm!("tlbi aside1, {}", in(reg) operand, options(nostack)),
+            Scope::InnerShareable => asm!("tlbi aside1is, {}", in(reg) operand, options(nostack)),
+        }
+    }
+    dsb_after_tlbi(scope);
+}
+
+fn tlb_flush_va_scoped(range: Range<Address<Virtual>>, asid: Option<Asid>, scope: Scope) {
+    let start = range.start.align_down_page().value();
+    let end = range.end.align_up_page().value();
+    if start >= end {
+        return;
+    }
+
+    if (end - start) >> KernelGranule::SHIFT > TLB_FLUSH_PAGE_THRESHOLD {
+        return match asid {
+            Some(asid) => tlb_flush_asid_scoped(asid, scope),
+            None => tlb_flush_all_scoped(scope),
+        };
+    }
+
+    barrier::dsb(barrier::ISHST);
+    for va in (start..end).step_by(KernelGranule::SIZE) {
+        // Without an ASID, entries of every ASID (and global entries) for the VA are invalidated.
+        let operand = tlbi_va_operand(va, asid.unwrap_or(0));
+        unsafe {
+            match (asid.is_some(), scope) {
+                (true, Scope::Local) => asm!("tlbi vae1, {}", in(reg) operand, options(nostack)),
+                (true, Scope::InnerShareable) => {
+                    asm!("tlbi vae1is, {}", in(reg) operand, options(nostack))
+                }
+                (false, Scope::Local) => asm!("tlbi vaae1, {}", in(reg) operand, options(nostack)),
+                (false, Scope::InnerShareable) => {
+                    asm!("tlbi vaae1is, {}", in(reg) operand, options(nostack))
+                }
+            }
+        }
+    }
+    dsb_after_tlbi(scope);
+}
+
+/// Invalidate all stage 1 EL1&0 TLB entries on the executing core.
+pub fn tlb_flush_all() {
+    tlb_flush_all_scoped(Scope::Local)
+}
+
+/// Invalidate all stage 1 EL1&0 TLB entries on every core of the inner shareable domain.
+pub fn tlb_flush_all_is() {
+    tlb_flush_all_scoped(Scope::InnerShareable)
+}
+
+/// Invalidate all non-global TLB entries tagged with `asid` on the executing core.
+pub fn tlb_flush_asid(asid: Asid) {
+    tlb_flush_asid_scoped(asid, Scope::Local)
+}
+
+/// Invalidate all non-global TLB entries tagged with `asid` on every core.
+pub fn tlb_flush_asid_is(asid: Asid) {
+    tlb_flush_asid_scoped(asid, Scope::InnerShareable)
+}
+
+/// Invalidate the TLB entries covering `range` on the executing core.
+///
+/// With `asid == None` the entries are invalidated for all ASIDs.
+pub fn tlb_flush_va(range: Range<Address<Virtual>>, asid: Option<Asid>) {
+    tlb_flush_va_scoped(range, asid, Scope::Local)
+}
+
+/// Invalidate the TLB entries covering `range` on every core.
+pub fn tlb_flush_va_is(range: Range<Address<Virtual>>, asid: Option<Asid>) {
+    tlb_flush_va_scoped(range, asid, Scope::InnerShareable)
+}
+
+#[inline(always)]
+fn ctr_el0() -> u64 {
+    let ctr: u64;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack)) };
+    ctr
+}
+
+/// Smallest data cache line size in bytes (CTR_EL0.DminLine).
+pub fn dcache_line_size() -> usize {
+    4 << ((ctr_el0() >> 16) & 0xf)
+}
+
+/// Smallest instruction cache line size in bytes (CTR_EL0.IminLine).
+pub fn icache_line_size() -> usize {
+    4 << (ctr_el0() & 0xf)
+}
+
+#[derive(Copy, Clone)]
+enum DcacheOp {
+    Clean,
+    CleanInvalidate,
+    Invalidate,
+    CleanToPoU,
+}
+
+fn dcache_range_op(range: Range<Address<Virtual>>, op: DcacheOp) {
+    let line = dcache_line_size();
+    let start = range.start.value() & !(line - 1);
+    let end = range.end.value();
+
+    for va in (start..end).step_by(line) {
+        unsafe {
+            match op {
+                DcacheOp::Clean => asm!("dc cvac, {}", in(reg) va, options(nostack)),
+                DcacheOp::CleanInvalidate => asm!("dc civac, {}", in(reg) va, options(nostack)),
+                DcacheOp::Invalidate => asm!("dc ivac, {}", in(reg) va, options(nostack)),
+                DcacheOp::CleanToPoU => asm!("dc cvau, {}", in(reg) va, options(nostack)),
+            }
+        }
+    }
+    barrier::dsb(barrier::SY);
+}
+
+/// Write dirty lines in `range` back to the point of coherency (`dc cvac`).
+///
+/// Use before a device reads memory the CPU has written, e.g. a DMA transmit buffer.
+pub fn dcache_clean_range(range: Range<Address<Virtual>>) {
+    dcache_range_op(range, DcacheOp::Clean)
+}
+
+/// Clean and invalidate the lines in `range` (`dc civac`).
+pub fn dcache_clean_invalidate_range(range: Range<Address<Virtual>>) {
+    dcache_range_op(range, DcacheOp::CleanInvalidate)
+}
+
+/// Discard the lines in `range` without writing them back (`dc ivac`).
+///
+/// Use after a device has written memory the CPU is about to read, e.g. a DMA receive buffer.
+/// Partial lines at both ends are cleaned first so neighbouring data is not lost.
+pub fn dcache_invalidate_range(range: Range<Address<Virtual>>) {
+    let line = dcache_line_size();
+    let (start, end) = (range.start.value(), range.end.value());
+
+    if start & (line - 1) != 0 {
+        dcache_range_op(
+            range.start..Address::new(start + 1),
+            DcacheOp::CleanInvalidate,
+        );
+    }
+    if end & (line - 1) != 0 {
+        dcache_range_op(Address::new(end - 1)..range.end, DcacheOp::CleanInvalidate);
+    }
+
+    dcache_range_op(range, DcacheOp::Invalidate)
+}
+
+/// Make instructions written to `range` visible to instruction fetches, e.g. after patching code.
+pub fn icache_sync_range(range: Range<Address<Virtual>>) {
+    dcache_range_op(range.clone(), DcacheOp::CleanToPoU);
+
+    let line = icache_line_size();
+    let start = range.start.value() & !(line - 1);
+    for va in (start..range.end.value()).step_by(line) {
+        unsafe { asm!("ic ivau, {}", in(reg) va, options(nostack)) };
+    }
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
+
+/// Invalidate the whole instruction cache of the executing core.
+pub fn icache_invalidate_all() {
+    unsafe { asm!("ic iallu", options(nostack)) };
+    barrier::dsb(barrier::NSH);
+    barrier::isb(barrier::SY);
+}
+
+/// Invalidate the whole instruction cache of every core.
+pub fn icache_invalidate_all_is() {
+    unsafe { asm!("ic ialluis", options(nostack)) };
+    barrier::dsb(barrier::ISH);
+    barrier::isb(barrier::SY);
+}
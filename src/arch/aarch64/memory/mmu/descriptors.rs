@@ -1,6 +1,7 @@
 use crate::memory::types::Granule64KB;
 use crate::memory::types::*;
 use aarch64_cpu::registers::{Readable, Writeable};
+use core::fmt;
 use tock_registers::{register_bitfields, registers::InMemoryRegister};
 
 // A level 3 page descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-17.
@@ -18,6 +19,13 @@ register_bitfields! {u64,
             True = 1
         ],
 
+        /// Dirty bit modifier. With TCR_EL1.HD, the MMU makes a read-only page writable on the
+        /// first write instead of faulting, which marks it dirty.
+        DBM      OFFSET(51) NUMBITS(1) [
+            False = 0,
+            True = 1
+        ],
+
         /// Physical address of the next table descriptor (lvl2) or the page descriptor (lvl3).
         OUTPUT_ADDR_64KiB OFFSET(16) NUMBITS(32) [], // [47:16]
 
@@ -29,6 +37,7 @@ register_bitfields! {u64,
 
         /// Shareability field.
         SH       OFFSET(8) NUMBITS(2) [
+            NonShareable = 0b00,
             OuterShareable = 0b10,
             InnerShareable = 0b11
         ],
@@ -90,6 +99,80 @@ impl PageDescriptor {
         InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value)
             .is_set(STAGE1_PAGE_DESCRIPTOR::VALID)
     }
+
+    pub fn output_page_addr(&self) -> PageAddress<Physical> {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value);
+        let shifted = val.read(STAGE1_PAGE_DESCRIPTOR::OUTPUT_ADDR_64KiB) as usize;
+        PageAddress::from(shifted << Granule64KB::SHIFT)
+    }
+
+    pub fn attributes(&self) -> PageAttributes {
+        let val = InMemoryRegister::<u64, STAGE1_PAGE_DESCRIPTOR::Register>::new(self.value);
+        PageAttributes {
+            attr_index: val.read(STAGE1_PAGE_DESCRIPTOR::AttrIndx) as u8,
+            ap: val.read(STAGE1_PAGE_DESCRIPTOR::AP) as u8,
+            sh: val.read(STAGE1_PAGE_DESCRIPTOR::SH) as u8,
+            pxn: val.is_set(STAGE1_PAGE_DESCRIPTOR::PXN),
+            uxn: val.is_set(STAGE1_PAGE_DESCRIPTOR::UXN),
+            af: val.is_set(STAGE1_PAGE_DESCRIPTOR::AF),
+            dbm: val.is_set(STAGE1_PAGE_DESCRIPTOR::DBM),
+        }
+    }
+}
+
+/// The attributes of a page descriptor, as the MMU sees them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PageAttributes {
+    /// Index into MAIR_EL1.
+    pub attr_index: u8,
+    /// AP[2:1]: bit 1 makes the page read-only, bit 0 gives EL0 access.
+    pub ap: u8,
+    pub sh: u8,
+    pub pxn: bool,
+    pub uxn: bool,
+    /// Access flag, set by the MMU on the first access with TCR_EL1.HA.
+    pub af: bool,
+    pub dbm: bool,
+}
+
+impl PageAttributes {
+    /// Whether a page with DBM set has been written to.
+    pub fn is_dirty(&self) -> bool {
+        self.dbm && self.ap & 0b10 == 0
+    }
+}
+
+impl fmt::Display for PageAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ap = match self.ap {
+            0b00 => "RW_EL1",
+            0b01 => "RW_EL1_EL0",
+            0b10 => "RO_EL1",
+            _ => "RO_EL1_EL0",
+        };
+        let sh = match self.sh {
+            0b00 => "NSH",
+            0b10 => "OSH",
+            0b11 => "ISH",
+            _ => "?SH",
+        };
+        let flag = |set: bool, name: &'static str| if set { name } else { "-" };
+        write!(
+            f,
+            "Attr{} {:<10} {} {:>3} {:>3} {:>2} {}",
+            self.attr_index,
+            ap,
+            sh,
+            flag(self.pxn, "PXN"),
+            flag(self.uxn, "UXN"),
+            flag(self.af, "AF"),
+            match (self.dbm, self.is_dirty()) {
+                (true, true) => "DBM dirty",
+                (true, false) => "DBM clean",
+                (false, _) => "-",
+            }
+        )
+    }
 }
 
 // A table descriptor, as per ARMv8-A Architecture Reference Manual Figure D5-15.
@@ -2,13 +2,38 @@ use crate::{
     arch::memory::mmu::descriptors::STAGE1_PAGE_DESCRIPTOR,
     memory::types::{AccessPermissions, AttributeFields, MemoryAttributes},
 };
+use aarch64_cpu::registers::MAIR_EL1;
 use core::convert;
+use tock_registers::fields::FieldValue;
 
 /// Constants for indexing the MAIR_EL1.
 #[allow(dead_code)]
 pub mod mair {
+    /// Device-nGnRE.
     pub const DEVICE: u64 = 0;
+    /// Normal, write-back non-transient, read and write allocate.
     pub const NORMAL: u64 = 1;
+    /// Device-nGnRnE.
+    pub const DEVICE_NGNRNE: u64 = 2;
+    /// Device-GRE.
+    pub const DEVICE_GRE: u64 = 3;
+    /// Normal, non-cacheable.
+    pub const NORMAL_NC: u64 = 4;
+    /// Normal, write-through non-transient, read and write allocate.
+    pub const NORMAL_WT: u64 = 5;
+}
+
+/// The attributes for each `mair` index, to program into MAIR_EL1.
+pub fn mair_el1() -> FieldValue<u64, MAIR_EL1::Register> {
+    MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck
+        + MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
+        + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc
+        + MAIR_EL1::Attr2_Device::nonGathering_nonReordering_noEarlyWriteAck
+        + MAIR_EL1::Attr3_Device::Gathering_Reordering_EarlyWriteAck
+        + MAIR_EL1::Attr4_Normal_Outer::NonCacheable
+        + MAIR_EL1::Attr4_Normal_Inner::NonCacheable
+        + MAIR_EL1::Attr5_Normal_Outer::WriteThrough_NonTransient_ReadWriteAlloc
+        + MAIR_EL1::Attr5_Normal_Inner::WriteThrough_NonTransient_ReadWriteAlloc
 }
 
 /// Convert the kernel's generic memory attributes to HW-specific attributes of the MMU.
@@ -17,16 +42,27 @@ impl convert::From<AttributeFields>
 {
     fn from(attribute_fields: AttributeFields) -> Self {
         // Memory attributes.
-        let mut desc = match attribute_fields.memory_attributes {
-            MemoryAttributes::CacheableDRAM => {
-                STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable
-                    + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::NORMAL)
+        // Device memory is always treated as outer shareable, whatever SH says.
+        let (index, sh) = match attribute_fields.memory_attributes {
+            MemoryAttributes::CacheableDRAM | MemoryAttributes::ReadOnlyAfterInit => {
+                (mair::NORMAL, STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable)
+            }
+            MemoryAttributes::WriteThrough => {
+                (mair::NORMAL_WT, STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable)
+            }
+            MemoryAttributes::NormalNonCacheable => {
+                (mair::NORMAL_NC, STAGE1_PAGE_DESCRIPTOR::SH::InnerShareable)
             }
-            MemoryAttributes::Device => {
-                STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable
-                    + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(mair::DEVICE)
+            MemoryAttributes::Device => (mair::DEVICE, STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable),
+            MemoryAttributes::Device_nGnRnE => (
+                mair::DEVICE_NGNRNE,
+                STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable,
+            ),
+            MemoryAttributes::Device_GRE => {
+                (mair::DEVICE_GRE, STAGE1_PAGE_DESCRIPTOR::SH::OuterShareable)
             }
         };
+        let mut desc = sh + STAGE1_PAGE_DESCRIPTOR::AttrIndx.val(index);
 
         // Access Permissions.
         desc += match attribute_fields.access_permissions {
@@ -1,12 +1,15 @@
 pub mod descriptors;
 pub mod mair;
+pub mod translate;
 pub mod translation_table;
 
+pub use translate::{hw_query, hw_translate, AccessKind, FaultInfo};
+
+use super::maintenance;
 use crate::bsp::memory::{symbols, KernelVirtAddrSpace};
 use crate::memory::types::*;
 use crate::memory::{self, mmu::error::MMUEnableError};
 use aarch64_cpu::{asm::barrier, registers::*};
-use core::arch::asm;
 use tock_registers::interfaces::{ReadWriteable, Readable};
 
 pub(super) static MMU: MemoryManagementUnit = MemoryManagementUnit;
@@ -20,10 +23,7 @@ impl memory::mmu::interface::MMU for MemoryManagementUnit {
         }
 
         // Setup MAIR: Prepare the memory attribute indirection register
-        let attr0 = MAIR_EL1::Attr0_Device::nonGathering_nonReordering_EarlyWriteAck;
-        let attr1 = MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc
-            + MAIR_EL1::Attr1_Normal_Inner::WriteBack_NonTransient_ReadWriteAlloc;
-        MAIR_EL1.write(attr0 + attr1);
+        MAIR_EL1.write(mair::mair_el1());
 
         // Set Kernel Table physical base address to TTBR0_EL1
         TTBR0_EL1.set_baddr(phys_table_baddr.value() as u64);
@@ -93,27 +93,8 @@ impl memory::mmu::interface::MMU for MemoryManagementUnit {
         barrier::dsb(barrier::ISHST);
         barrier::isb(barrier::SY);
 
-        unsafe {
-            asm!(
-                "isb
-                tlbi vmalle1
-                ic iallu
-                dsb nsh
-                isb"
-            );
-        }
-
-        let vaddr: u64 = 0x4234_5678;
-        unsafe {
-            asm!(
-                "MOV X0, {0:x}",
-                "AT S1E1R, X0",
-                in(reg) vaddr,
-            );
-        }
-        // __println!("PAR_EL1: {:#x}", PAR_EL1.get());
-        // __println!("PAR_EL1::F: {:#x}", PAR_EL1.read(PAR_EL1::F));
-        // __println!("PAR_EL1::PA: {:#x}", PAR_EL1.read(PAR_EL1::PA));
+        maintenance::tlb_flush_all();
+        maintenance::icache_invalidate_all();
 
         barrier::isb(barrier::SY);
 
@@ -0,0 +1,297 @@
+// Address translation by the MMU itself
+//
+// `AT S1E1R/W` and `AT S1E0R/W` run a stage 1 translation as a load or store from EL1 or EL0 would,
+// and leave either the output address and its attributes or the fault that access would take in
+// PAR_EL1. The result reflects the TLBs and the tables as the hardware sees them, which makes it a
+// check on what the kernel believes it has mapped.
+
+use super::descriptors::PageAttributes;
+use crate::arch::irq;
+use crate::memory::translation_table::interface::TranslationTable;
+use crate::memory::types::*;
+use aarch64_cpu::asm::barrier;
+use aarch64_cpu::registers::{MAIR_EL1, PAR_EL1};
+use core::arch::asm;
+use core::fmt;
+use log::{info, warn};
+use tock_registers::interfaces::Readable;
+use tock_registers::{register_bitfields, registers::InMemoryRegister};
+
+// PAR_EL1, as per ARMv8-A Architecture Reference Manual D17.2.112.
+register_bitfields! {u64,
+    PAR [
+        /// MAIR_EL1 encoding of the memory attributes, on success.
+        ATTR OFFSET(56) NUMBITS(8) [],
+        /// Output address bits [47:12], on success.
+        PA   OFFSET(12) NUMBITS(36) [],
+        /// Non-secure output, on success.
+        NS   OFFSET(9) NUMBITS(1) [],
+        /// Shareability, on success.
+        SH   OFFSET(7) NUMBITS(2) [],
+        /// Stage 2 fault, on failure.
+        S    OFFSET(9) NUMBITS(1) [],
+        /// Fault on a stage 2 walk of a stage 1 table, on failure.
+        PTW  OFFSET(8) NUMBITS(1) [],
+        /// Fault status, on failure.
+        FST  OFFSET(1) NUMBITS(6) [],
+        F    OFFSET(0) NUMBITS(1) []
+    ]
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AccessKind {
+    /// A load from EL1, `AT S1E1R`.
+    Read,
+    /// A store from EL1, `AT S1E1W`.
+    Write,
+    /// A load from EL0, `AT S1E0R`.
+    UserRead,
+    /// A store from EL0, `AT S1E0W`.
+    UserWrite,
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            AccessKind::Read => "EL1 read",
+            AccessKind::Write => "EL1 write",
+            AccessKind::UserRead => "EL0 read",
+            AccessKind::UserWrite => "EL0 write",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FaultKind {
+    AddressSize,
+    Translation,
+    AccessFlag,
+    Permission,
+    ExternalAbort,
+    /// An external abort on a table walk.
+    ExternalAbortOnWalk,
+    Alignment,
+    TlbConflict,
+    Other,
+}
+
+/// Why a translation failed, decoded from PAR_EL1.FST.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FaultInfo {
+    pub kind: FaultKind,
+    /// Translation table level the fault was taken at, where the status has one.
+    pub level: Option<u8>,
+    /// The raw fault status code.
+    pub status: u8,
+    /// Taken in a stage 2 walk of a stage 1 table.
+    pub table_walk: bool,
+    pub stage2: bool,
+}
+
+impl FaultInfo {
+    fn from_status(status: u8, table_walk: bool, stage2: bool) -> Self {
+        let level = status & 0b11;
+        let (kind, level) = match status >> 2 {
+            0b0000 => (FaultKind::AddressSize, Some(level)),
+            0b0001 => (FaultKind::Translation, Some(level)),
+            0b0010 => (FaultKind::AccessFlag, Some(level)),
+            0b0011 => (FaultKind::Permission, Some(level)),
+            0b0101 => (FaultKind::ExternalAbortOnWalk, Some(level)),
+            _ => match status {
+                0b010000 => (FaultKind::ExternalAbort, None),
+                0b100001 => (FaultKind::Alignment, None),
+                0b110000 => (FaultKind::TlbConflict, None),
+                _ => (FaultKind::Other, None),
+            },
+        };
+        Self {
+            kind,
+            level,
+            status,
+            table_walk,
+            stage2,
+        }
+    }
+}
+
+impl fmt::Display for FaultInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            FaultKind::AddressSize => "address size fault",
+            FaultKind::Translation => "translation fault",
+            FaultKind::AccessFlag => "access flag fault",
+            FaultKind::Permission => "permission fault",
+            FaultKind::ExternalAbort => "synchronous external abort",
+            FaultKind::ExternalAbortOnWalk => "synchronous external abort on table walk",
+            FaultKind::Alignment => "alignment fault",
+            FaultKind::TlbConflict => "TLB conflict abort",
+            FaultKind::Other => "fault",
+        };
+        write!(f, "{}", kind)?;
+        if let Some(level) = self.level {
+            write!(f, ", level {}", level)?;
+        }
+        write!(f, " (FST {:#04x})", self.status)?;
+        if self.stage2 {
+            write!(f, ", stage 2")?;
+        }
+        if self.table_walk {
+            write!(f, ", on table walk")?;
+        }
+        Ok(())
+    }
+}
+
+/// A successful translation, decoded from PAR_EL1.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Translation {
+    pub phys_addr: Address<Physical>,
+    /// Memory attributes in the MAIR_EL1 encoding.
+    pub attr: u8,
+    /// Shareability, as in the page descriptor's SH field.
+    pub sh: u8,
+    pub non_secure: bool,
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sh = match self.sh {
+            0b00 => "NSH",
+            0b10 => "OSH",
+            0b11 => "ISH",
+            _ => "?SH",
+        };
+        // Device memory has the upper nibble clear.
+        let memory = if self.attr & 0xf0 == 0 {
+            "Device"
+        } else {
+            "Normal"
+        };
+        write!(
+            f,
+            "{:#x} {} attr {:#04x} {}",
+            self.phys_addr.value(),
+            memory,
+            self.attr,
+            sh
+        )?;
+        if self.non_secure {
+            write!(f, " NS")?;
+        }
+        Ok(())
+    }
+}
+
+/// Asks the MMU to translate `virt_addr` for an access of `kind`.
+pub fn hw_query(virt_addr: Address<Virtual>, kind: AccessKind) -> Result<Translation, FaultInfo> {
+    let va = virt_addr.value();
+    // PAR_EL1 would be overwritten by an AT in an interrupt handler.
+    let par = irq::exec_with_irq_disabled(|| {
+        unsafe {
+            match kind {
+                AccessKind::Read => asm!("at s1e1r, {}", in(reg) va, options(nostack)),
+                AccessKind::Write => asm!("at s1e1w, {}", in(reg) va, options(nostack)),
+                AccessKind::UserRead => asm!("at s1e0r, {}", in(reg) va, options(nostack)),
+                AccessKind::UserWrite => asm!("at s1e0w, {}", in(reg) va, options(nostack)),
+            }
+        }
+        barrier::isb(barrier::SY);
+        PAR_EL1.get()
+    });
+
+    let par = InMemoryRegister::<u64, PAR::Register>::new(par);
+    if par.is_set(PAR::F) {
+        return Err(FaultInfo::from_status(
+            par.read(PAR::FST) as u8,
+            par.is_set(PAR::PTW),
+            par.is_set(PAR::S),
+        ));
+    }
+
+    let page = (par.read(PAR::PA) as usize) << 12;
+    Ok(Translation {
+        phys_addr: Address::new(page | (va & 0xfff)),
+        attr: par.read(PAR::ATTR) as u8,
+        sh: par.read(PAR::SH) as u8,
+        non_secure: par.is_set(PAR::NS),
+    })
+}
+
+/// The physical address the MMU translates `virt_addr` to for an access of `kind`.
+pub fn hw_translate(
+    virt_addr: Address<Virtual>,
+    kind: AccessKind,
+) -> Result<Address<Physical>, FaultInfo> {
+    hw_query(virt_addr, kind).map(|translation| translation.phys_addr)
+}
+
+/// Checks one page against the MMU and reports what differs. Returns whether anything did.
+fn check_page(
+    virt_addr: Address<Virtual>,
+    phys_addr: Address<Physical>,
+    attributes: PageAttributes,
+    mair: u64,
+) -> bool {
+    let translation = match hw_query(virt_addr, AccessKind::Read) {
+        Ok(translation) => translation,
+        Err(fault) => {
+            warn!("      {}: mapped in the tables, but {}", virt_addr, fault);
+            return true;
+        }
+    };
+
+    let mut differs = false;
+    if translation.phys_addr != phys_addr {
+        warn!(
+            "      {}: tables map to {}, the MMU to {}",
+            virt_addr, phys_addr, translation.phys_addr
+        );
+        differs = true;
+    }
+
+    let attr = (mair >> (attributes.attr_index * 8)) as u8;
+    if translation.attr != attr {
+        warn!(
+            "      {}: tables give attr {:#04x}, the MMU {:#04x}",
+            virt_addr, attr, translation.attr
+        );
+        differs = true;
+    }
+
+    // With DBM set, the MMU makes a read-only page writable on the first write.
+    let writable = attributes.ap & 0b10 == 0 || attributes.dbm;
+    match hw_query(virt_addr, AccessKind::Write) {
+        Err(fault) if writable => {
+            warn!("      {}: writable in the tables, but {}", virt_addr, fault);
+            differs = true;
+        }
+        Ok(_) if !writable => {
+            warn!("      {}: read-only in the tables, but writable", virt_addr);
+            differs = true;
+        }
+        _ => {}
+    }
+    differs
+}
+
+/// Compares every page mapped in `table` with what the MMU makes of it: the output address, the
+/// memory attributes and whether a write is allowed. `table` has to be the live one. Returns how
+/// many pages differ.
+pub fn check_table(table: &impl TranslationTable<PageAttributes = PageAttributes>) -> usize {
+    let mair = MAIR_EL1.get();
+    let mut pages = 0;
+    let mut mismatches = 0;
+    table.for_each_page(&mut |virt, phys, attributes| {
+        pages += 1;
+        if check_page(virt.inner(), phys.inner(), attributes, mair) {
+            mismatches += 1;
+        }
+    });
+
+    info!(
+        "      Checked {} pages against the MMU, {} differ",
+        pages, mismatches
+    );
+    mismatches
+}
@@ -1,4 +1,4 @@
-use super::descriptors::{PageDescriptor, TableDescriptor};
+use super::descriptors::{PageAttributes, PageDescriptor, TableDescriptor};
 use crate::{
     bsp::memory::symbols,
     memory::{self, types::*},
@@ -23,6 +23,9 @@ pub struct FixedSizeTranslationTable<const NUM_TABLES: usize> {
     /// Page descriptors, covering 64 KiB windows per entry.
     l3: [[PageDescriptor; 8192]; NUM_TABLES],
 
+    /// Valid pages in each l3 table, so that walks can skip the empty ones.
+    l3_valid: [u16; NUM_TABLES],
+
     /// Have the tables been initialized?
     pub initialized: bool,
 }
@@ -33,6 +36,7 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
         Self {
             l2: [TableDescriptor::new(); NUM_TABLES],
             l3: [[PageDescriptor::new(); 8192]; NUM_TABLES],
+            l3_valid: [0; NUM_TABLES],
             initialized: false,
         }
     }
@@ -88,6 +92,7 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
         }
 
         *desc = *page;
+        self.l3_valid[l2_idx] += 1;
         Ok(())
     }
 
@@ -103,6 +108,8 @@ impl<const NUM_TABLES: usize> FixedSizeTranslationTable<NUM_TABLES> {
 impl<const NUM_TABLES: usize> memory::translation_table::interface::TranslationTable
     for FixedSizeTranslationTable<NUM_TABLES>
 {
+    type PageAttributes = PageAttributes;
+
     #[inline(always)]
     fn init(&mut self) {
         if self.initialized {
@@ -154,6 +161,58 @@ impl<const NUM_TABLES: usize> memory::translation_table::interface::TranslationT
 
         Ok(())
     }
+
+    fn set_attributes(
+        &mut self,
+        virt_region: &MemoryRegion<Virtual>,
+        attributes: &AttributeFields,
+    ) -> Result<(), &'static str> {
+        if !self.initialized {
+            return Err("Translation table is not initialized");
+        }
+
+        // Check first so that a hole leaves the region unchanged.
+        if virt_region
+            .into_iter()
+            .any(|virt_page_addr| !self.get_page(virt_page_addr).is_valid())
+        {
+            return Err("Virtual page is not mapped");
+        }
+
+        for virt_page_addr in virt_region.into_iter() {
+            let l2_idx = Self::l2_idx(virt_page_addr)?;
+            let l3_idx = Self::l3_idx(virt_page_addr)?;
+            let desc = &mut self.l3[l2_idx][l3_idx];
+            *desc = PageDescriptor::from_output_page_addr(desc.output_page_addr(), attributes);
+        }
+        Ok(())
+    }
+
+    fn for_each_page(
+        &self,
+        f: &mut dyn FnMut(PageAddress<Virtual>, PageAddress<Physical>, PageAttributes),
+    ) {
+        if !self.initialized {
+            return;
+        }
+
+        for (l2_idx, l3) in self.l3.iter().enumerate() {
+            if self.l3_valid[l2_idx] == 0 {
+                continue;
+            }
+            for (l3_idx, desc) in l3.iter().enumerate() {
+                if !desc.is_valid() {
+                    continue;
+                }
+                let virt_addr = (l2_idx << Granule512MB::SHIFT) | (l3_idx << Granule64KB::SHIFT);
+                f(
+                    PageAddress::from(virt_addr),
+                    desc.output_page_addr(),
+                    desc.attributes(),
+                );
+            }
+        }
+    }
 }
 
 impl<const SIZE: usize> memory::address_space::AssociatedTranslationTable
@@ -1,3 +1,4 @@
+pub mod maintenance;
 pub mod mmu;
 use mmu::MMU;
 
@@ -11,21 +12,17 @@ pub fn mmu() -> &'static impl memory::mmu::interface::MMU {
 }
 
 pub fn get_ramrange() -> (u64, MemorySize) {
-    let mem_devt = devicetree::get_property("/memory", "device_type").unwrap();
+    let memory = devicetree::Node::find("/memory").expect("No memory node in device tree");
 
     assert!(
-        core::str::from_utf8(mem_devt)
-            .unwrap()
-            .trim_matches(char::from(0))
-            == "memory"
+        memory
+            .property("device_type")
+            .and_then(|property| property.as_str())
+            == Some("memory")
     );
 
-    let mem_reg = devicetree::get_property("/memory", "reg").unwrap();
-    let (start, size) = mem_reg.split_at(core::mem::size_of::<u64>());
-    let ram_start = u64::from_be_bytes(start.try_into().unwrap());
-    let ram_size = usize::from_be_bytes(size.try_into().unwrap());
-
-    (ram_start, MemorySize(ram_size))
+    let reg = memory.reg_at(0).expect("Invalid memory reg");
+    (reg.address as u64, MemorySize(reg.size))
 }
 
 pub fn print_ram_info() {
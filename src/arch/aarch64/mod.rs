@@ -1,15 +1,18 @@
 pub mod console;
 pub mod drivers;
 pub mod exception;
+pub mod kaslr;
 pub mod memory;
+pub mod psci;
+pub mod semihosting;
 pub mod start;
 pub mod test;
 pub mod timer;
-pub mod semihosting;
 
 pub use exception::irq;
 
 use aarch64_cpu::asm;
+use aarch64_cpu::registers::{Readable, MPIDR_EL1};
 use drivers::devicetree;
 
 pub fn get_cpus() -> usize {
@@ -18,6 +21,11 @@ pub fn get_cpus() -> usize {
         .count()
 }
 
+/// The calling core's number, from the affinity level 0 field of MPIDR_EL1.
+pub fn cpu_id() -> usize {
+    (MPIDR_EL1.get() & 0xff) as usize
+}
+
 pub fn halt() {
     asm::wfi();
 }
@@ -0,0 +1,221 @@
+// Power State Coordination Interface (ARM DEN 0022)
+//
+// The firmware (or QEMU) implements PSCI and is reached through either `smc` or `hvc`, as given by
+// the `method` property of the `/psci` device tree node.
+
+use crate::arch::drivers::devicetree;
+use crate::sync::spinlock::RawSpinlock;
+use core::arch::asm;
+use core::fmt::Display;
+use generic_once_cell::OnceCell;
+use log::info;
+
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_SUSPEND: u32 = 0xC400_0001;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+pub const PSCI_FEATURES: u32 = 0x8400_000A;
+
+static PSCI: OnceCell<RawSpinlock, Psci> = OnceCell::new();
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Conduit {
+    Smc,
+    Hvc,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PsciError {
+    NotSupported,
+    InvalidParameters,
+    Denied,
+    AlreadyOn,
+    OnPending,
+    InternalFailure,
+    NotPresent,
+    Disabled,
+    InvalidAddress,
+    Unknown(i32),
+}
+
+impl From<i32> for PsciError {
+    fn from(value: i32) -> Self {
+        match value {
+            -1 => PsciError::NotSupported,
+            -2 => PsciError::InvalidParameters,
+            -3 => PsciError::Denied,
+            -4 => PsciError::AlreadyOn,
+            -5 => PsciError::OnPending,
+            -6 => PsciError::InternalFailure,
+            -7 => PsciError::NotPresent,
+            -8 => PsciError::Disabled,
+            -9 => PsciError::InvalidAddress,
+            e => PsciError::Unknown(e),
+        }
+    }
+}
+
+impl Display for PsciError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PsciError::NotSupported => write!(f, "NOT_SUPPORTED"),
+            PsciError::InvalidParameters => write!(f, "INVALID_PARAMETERS"),
+            PsciError::Denied => write!(f, "DENIED"),
+            PsciError::AlreadyOn => write!(f, "ALREADY_ON"),
+            PsciError::OnPending => write!(f, "ON_PENDING"),
+            PsciError::InternalFailure => write!(f, "INTERNAL_FAILURE"),
+            PsciError::NotPresent => write!(f, "NOT_PRESENT"),
+            PsciError::Disabled => write!(f, "DISABLED"),
+            PsciError::InvalidAddress => write!(f, "INVALID_ADDRESS"),
+            PsciError::Unknown(e) => write!(f, "Unknown PSCI error {}", e),
+        }
+    }
+}
+
+pub struct Psci {
+    conduit: Conduit,
+    version: (u16, u16),
+}
+
+impl Psci {
+    fn call(&self, fid: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+        let mut ret = fid as u64;
+        unsafe {
+            match self.conduit {
+                Conduit::Smc => asm!(
+                    "smc #0",
+                    inout("x0") ret,
+                    inout("x1") arg0 => _,
+                    inout("x2") arg1 => _,
+                    inout("x3") arg2 => _,
+                    out("x4") _, out("x5") _, out("x6") _, out("x7") _,
+                    out("x8") _, out("x9") _, out("x10") _, out("x11") _,
+                    out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                    out("x16") _, out("x17") _,
+                    options(nostack)
+                ),
+                Conduit::Hvc => asm!(
+                    "hvc #0",
+                    inout("x0") ret,
+                    inout("x1") arg0 => _,
+                    inout("x2") arg1 => _,
+                    inout("x3") arg2 => _,
+                    out("x4") _, out("x5") _, out("x6") _, out("x7") _,
+                    out("x8") _, out("x9") _, out("x10") _, out("x11") _,
+                    out("x12") _, out("x13") _, out("x14") _, out("x15") _,
+                    out("x16") _, out("x17") _,
+                    options(nostack)
+                ),
+            }
+        }
+        ret as i64
+    }
+
+    fn call_result(&self, fid: u32, arg0: u64, arg1: u64, arg2: u64) -> Result<(), PsciError> {
+        match self.call(fid, arg0, arg1, arg2) as i32 {
+            0 => Ok(()),
+            e => Err(PsciError::from(e)),
+        }
+    }
+
+    pub fn version(&self) -> (u16, u16) {
+        self.version
+    }
+
+    /// Checks whether the firmware implements the function `fid` (PSCI 1.0+).
+    pub fn supports(&self, fid: u32) -> bool {
+        if self.version.0 < 1 {
+            // PSCI_FEATURES is not available before 1.0, assume the 0.2 mandatory set.
+            return matches!(
+                fid,
+                PSCI_VERSION | CPU_SUSPEND | CPU_OFF | SYSTEM_OFF | SYSTEM_RESET
+            );
+        }
+        self.call(PSCI_FEATURES, fid as u64, 0, 0) >= 0
+    }
+}
+
+// PSCI reports its version, so it has to wait for the console.
+crate::initcall!(late, init);
+
+pub fn init() -> Result<(), &'static str> {
+    let method = devicetree::Node::find("/psci")
+        .ok_or("PSCI node not found")?
+        .property("method")
+        .ok_or("PSCI method not found")?;
+    let conduit = match method.as_str().ok_or("Invalid PSCI method")? {
+        "smc" => Conduit::Smc,
+        "hvc" => Conduit::Hvc,
+        _ => return Err("Unknown PSCI conduit"),
+    };
+
+    let mut psci = Psci {
+        conduit,
+        version: (0, 0),
+    };
+    let version = psci.call(PSCI_VERSION, 0, 0, 0) as u32;
+    psci.version = ((version >> 16) as u16, version as u16);
+
+    info!(
+        "PSCI v{}.{} via {}",
+        psci.version.0,
+        psci.version.1,
+        match conduit {
+            Conduit::Smc => "smc",
+            Conduit::Hvc => "hvc",
+        }
+    );
+
+    PSCI.set(psci).map_err(|_| "PSCI already initialized")
+}
+
+pub fn psci() -> Option<&'static Psci> {
+    PSCI.get()
+}
+
+/// Powers off the whole system. Only returns on failure.
+pub fn system_off() -> PsciError {
+    match psci() {
+        Some(psci) => psci
+            .call_result(SYSTEM_OFF, 0, 0, 0)
+            .err()
+            .unwrap_or(PsciError::Unknown(0)),
+        None => PsciError::NotPresent,
+    }
+}
+
+/// Resets the whole system. Only returns on failure.
+pub fn system_reset() -> PsciError {
+    match psci() {
+        Some(psci) => psci
+            .call_result(SYSTEM_RESET, 0, 0, 0)
+            .err()
+            .unwrap_or(PsciError::Unknown(0)),
+        None => PsciError::NotPresent,
+    }
+}
+
+/// Powers down the calling core. Only returns on failure.
+pub fn cpu_off() -> PsciError {
+    match psci() {
+        Some(psci) => psci
+            .call_result(CPU_OFF, 0, 0, 0)
+            .err()
+            .unwrap_or(PsciError::Unknown(0)),
+        None => PsciError::NotPresent,
+    }
+}
+
+/// Suspends the calling core.
+///
+/// For standby states `power_state` returns once the core is woken up. For powerdown states the
+/// core resumes at `entry_point` with `context_id` in x0 instead of returning.
+pub fn cpu_suspend(power_state: u32, entry_point: u64, context_id: u64) -> Result<(), PsciError> {
+    psci().ok_or(PsciError::NotPresent)?.call_result(
+        CPU_SUSPEND,
+        power_state as u64,
+        entry_point,
+        context_id,
+    )
+}
@@ -0,0 +1,128 @@
+// Console backed by the semihosting host's terminal (`:tt`)
+
+use super::{open, readc, write, FileHandle, OpenMode};
+use crate::{console, interrupt};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+pub static SEMIHOSTING_CONSOLE: SemihostingConsole = SemihostingConsole::new();
+
+// Every request traps into the host, so this is only a console when asked for, with the least
+// severe log level to print there, e.g. `semihosting.console=warn`.
+#[cfg(feature = "semihosting")]
+crate::param!(CONSOLE_LEVEL: Option<log::LevelFilter> = "semihosting.console", None);
+
+const NO_HANDLE: u64 = u64::MAX;
+
+pub struct SemihostingConsole {
+    stdout: AtomicU64,
+    chars_written: AtomicUsize,
+    chars_read: AtomicUsize,
+}
+
+impl SemihostingConsole {
+    pub const fn new() -> Self {
+        Self {
+            stdout: AtomicU64::new(NO_HANDLE),
+            chars_written: AtomicUsize::new(0),
+            chars_read: AtomicUsize::new(0),
+        }
+    }
+
+    fn stdout(&self) -> Option<FileHandle> {
+        let handle = self.stdout.load(Ordering::Acquire);
+        if handle != NO_HANDLE {
+            return Some(FileHandle(handle));
+        }
+
+        let handle = open(c":tt", OpenMode::Write).ok()?;
+        self.stdout.store(handle.0, Ordering::Release);
+        Some(handle)
+    }
+
+    fn write_bytes(&self, bytes: &[u8]) -> fmt::Result {
+        let handle = self.stdout().ok_or(fmt::Error)?;
+        let written = write(handle, bytes).map_err(|_| fmt::Error)?;
+        self.chars_written.fetch_add(written, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Default for SemihostingConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl console::interface::Write for SemihostingConsole {
+    fn write_char(&self, c: char) {
+        let mut buf = [0u8; 4];
+        let _ = self.write_bytes(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        struct Adapter<'a>(&'a SemihostingConsole);
+
+        impl fmt::Write for Adapter<'_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.0.write_bytes(s.as_bytes())
+            }
+        }
+
+        fmt::Write::write_fmt(&mut Adapter(self), args)
+    }
+
+    // The host takes everything in one call.
+    fn try_write(&self, bytes: &[u8]) -> usize {
+        match self.write_bytes(bytes) {
+            Ok(()) => bytes.len(),
+            Err(_) => 0,
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl console::interface::Read for SemihostingConsole {
+    fn read_byte(&self) -> u8 {
+        self.chars_read.fetch_add(1, Ordering::Relaxed);
+        readc()
+    }
+
+    // Reading blocks until the host has a character.
+    fn try_read_byte(&self) -> Option<u8> {
+        None
+    }
+
+    // The host buffers input line by line, there is nothing to drain.
+    fn clear_rx(&self) {}
+}
+
+impl console::interface::Statistics for SemihostingConsole {
+    fn chars_written(&self) -> usize {
+        self.chars_written.load(Ordering::Relaxed)
+    }
+
+    fn chars_read(&self) -> usize {
+        self.chars_read.load(Ordering::Relaxed)
+    }
+}
+
+impl interrupt::interface::IRQHandler for SemihostingConsole {
+    fn handler(&self, _cb: fn()) {}
+}
+
+impl console::interface::Console for SemihostingConsole {}
+
+#[cfg(feature = "semihosting")]
+fn init() -> Result<(), &'static str> {
+    match CONSOLE_LEVEL.get() {
+        // Reading blocks in the host, so it never takes input.
+        Some(level) => console::register_console("semihosting", &SEMIHOSTING_CONSOLE, level, false),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "semihosting")]
+// After the drivers, so that what the early console buffered goes to a UART.
+crate::initcall!(late, init);
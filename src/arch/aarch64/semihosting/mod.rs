@@ -1,19 +1,254 @@
+// ARM semihosting (ARM IHI 0076)
+//
+// Requests are issued with `hlt #0xF000`: the operation number goes in x0, the parameter (usually
+// the address of a parameter block) in x1, and the result comes back in x0. Only usable when the
+// host has semihosting enabled, e.g. `qemu -semihosting`.
+
+pub mod console;
+
 use core::arch::asm;
+use core::ffi::CStr;
+use core::fmt::Display;
+
+pub const SYS_OPEN: u64 = 0x01;
+pub const SYS_CLOSE: u64 = 0x02;
+pub const SYS_WRITEC: u64 = 0x03;
+pub const SYS_WRITE0: u64 = 0x04;
+pub const SYS_WRITE: u64 = 0x05;
+pub const SYS_READ: u64 = 0x06;
+pub const SYS_READC: u64 = 0x07;
+pub const SYS_ISERROR: u64 = 0x08;
+pub const SYS_ISTTY: u64 = 0x09;
+pub const SYS_SEEK: u64 = 0x0A;
+pub const SYS_FLEN: u64 = 0x0C;
+pub const SYS_REMOVE: u64 = 0x0E;
+pub const SYS_RENAME: u64 = 0x0F;
+pub const SYS_CLOCK: u64 = 0x10;
+pub const SYS_TIME: u64 = 0x11;
+pub const SYS_ERRNO: u64 = 0x13;
+pub const SYS_GET_CMDLINE: u64 = 0x15;
+pub const SYS_HEAPINFO: u64 = 0x16;
+pub const SYS_EXIT: u64 = 0x18;
+pub const SYS_ELAPSED: u64 = 0x30;
+pub const SYS_TICKFREQ: u64 = 0x31;
 
-pub fn semihosting_call(num: u64, arg0: u64, arg1: u64) {
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+/// Issues a semihosting request and returns the value the host placed in x0.
+pub fn semihosting_call(num: u64, arg: u64) -> u64 {
+    let mut ret = num;
     unsafe {
         asm!(
             "hlt #0xF000",
-            in("x0") num,
-            in("x1") arg0,
-            in("x2") arg1,
+            inout("x0") ret,
+            in("x1") arg,
             options(nostack)
         );
     }
+    ret
+}
+
+/// Host `errno` value describing why the last request failed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Errno(pub i32);
+
+impl Display for Errno {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "semihosting errno {}", self.0)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Errno>;
+
+/// Maps the conventional `-1` failure return to the host errno.
+fn check(ret: u64) -> Result<u64> {
+    if ret as i64 == -1 {
+        Err(errno())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// `fopen()` modes as numbered by the specification.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OpenMode {
+    Read = 0,
+    ReadBinary = 1,
+    ReadUpdate = 2,
+    ReadUpdateBinary = 3,
+    Write = 4,
+    WriteBinary = 5,
+    WriteUpdate = 6,
+    WriteUpdateBinary = 7,
+    Append = 8,
+    AppendBinary = 9,
+    AppendUpdate = 10,
+    AppendUpdateBinary = 11,
 }
 
+/// Host file handle returned by [`open`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FileHandle(u64);
 
-pub fn semihosting_exit(status: u64) {
+impl FileHandle {
+    pub fn raw(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Opens `path` on the host. The special path `:tt` refers to the host console.
+pub fn open(path: &CStr, mode: OpenMode) -> Result<FileHandle> {
+    let block: [u64; 3] = [
+        path.as_ptr() as u64,
+        mode as u64,
+        path.to_bytes().len() as u64,
+    ];
+    check(semihosting_call(SYS_OPEN, block.as_ptr() as u64)).map(FileHandle)
+}
+
+pub fn close(handle: FileHandle) -> Result<()> {
+    let block: [u64; 1] = [handle.0];
+    check(semihosting_call(SYS_CLOSE, block.as_ptr() as u64)).map(|_| ())
+}
+
+/// Writes `buf` to `handle`, returning the number of bytes written.
+pub fn write(handle: FileHandle, buf: &[u8]) -> Result<usize> {
+    let block: [u64; 3] = [handle.0, buf.as_ptr() as u64, buf.len() as u64];
+    // The host returns the number of bytes that were *not* written.
+    let remaining = semihosting_call(SYS_WRITE, block.as_ptr() as u64) as usize;
+    if remaining >= buf.len() && !buf.is_empty() {
+        return Err(errno());
+    }
+    Ok(buf.len() - remaining)
+}
+
+/// Reads into `buf` from `handle`, returning the number of bytes read. Zero means end of file.
+pub fn read(handle: FileHandle, buf: &mut [u8]) -> Result<usize> {
+    let block: [u64; 3] = [handle.0, buf.as_mut_ptr() as u64, buf.len() as u64];
+    // The host returns the number of bytes that were *not* read, all of them at end of file.
+    let remaining = semihosting_call(SYS_READ, block.as_ptr() as u64) as usize;
+    Ok(buf.len() - remaining.min(buf.len()))
+}
+
+/// Moves the file position of `handle` to the absolute offset `pos`.
+pub fn seek(handle: FileHandle, pos: usize) -> Result<()> {
+    let block: [u64; 2] = [handle.0, pos as u64];
+    match semihosting_call(SYS_SEEK, block.as_ptr() as u64) as i64 {
+        0 => Ok(()),
+        _ => Err(errno()),
+    }
+}
+
+/// Length of the file behind `handle` in bytes.
+pub fn flen(handle: FileHandle) -> Result<usize> {
+    let block: [u64; 1] = [handle.0];
+    check(semihosting_call(SYS_FLEN, block.as_ptr() as u64)).map(|len| len as usize)
+}
+
+/// Whether `handle` refers to an interactive device.
+pub fn istty(handle: FileHandle) -> bool {
+    let block: [u64; 1] = [handle.0];
+    semihosting_call(SYS_ISTTY, block.as_ptr() as u64) == 1
+}
+
+pub fn remove(path: &CStr) -> Result<()> {
+    let block: [u64; 2] = [path.as_ptr() as u64, path.to_bytes().len() as u64];
+    match semihosting_call(SYS_REMOVE, block.as_ptr() as u64) {
+        0 => Ok(()),
+        _ => Err(errno()),
+    }
+}
+
+pub fn rename(from: &CStr, to: &CStr) -> Result<()> {
+    let block: [u64; 4] = [
+        from.as_ptr() as u64,
+        from.to_bytes().len() as u64,
+        to.as_ptr() as u64,
+        to.to_bytes().len() as u64,
+    ];
+    match semihosting_call(SYS_RENAME, block.as_ptr() as u64) {
+        0 => Ok(()),
+        _ => Err(errno()),
+    }
+}
+
+/// Writes a single character to the host console.
+pub fn writec(c: u8) {
+    semihosting_call(SYS_WRITEC, &c as *const u8 as u64);
+}
+
+/// Writes a NUL-terminated string to the host console.
+pub fn write0(s: &CStr) {
+    semihosting_call(SYS_WRITE0, s.as_ptr() as u64);
+}
+
+/// Reads a single character from the host console, blocking until one is available.
+pub fn readc() -> u8 {
+    semihosting_call(SYS_READC, 0) as u8
+}
+
+/// Centiseconds since the program started.
+pub fn clock() -> Result<u64> {
+    check(semihosting_call(SYS_CLOCK, 0))
+}
+
+/// Seconds since 00:00 January 1, 1970 on the host.
+pub fn time() -> u64 {
+    semihosting_call(SYS_TIME, 0)
+}
+
+/// Ticks since the program started, see [`tickfreq`].
+pub fn elapsed() -> Result<u64> {
+    let mut ticks: u64 = 0;
+    match semihosting_call(SYS_ELAPSED, &mut ticks as *mut u64 as u64) as i64 {
+        0 => Ok(ticks),
+        _ => Err(errno()),
+    }
+}
+
+/// Frequency of the [`elapsed`] tick counter in Hz.
+pub fn tickfreq() -> Result<u64> {
+    check(semihosting_call(SYS_TICKFREQ, 0))
+}
+
+/// The host's errno for the last failed request.
+pub fn errno() -> Errno {
+    Errno(semihosting_call(SYS_ERRNO, 0) as i32)
+}
+
+/// Copies the command line the emulator was started with into `buf`.
+pub fn get_cmdline(buf: &mut [u8]) -> Result<&str> {
+    let mut block: [u64; 2] = [buf.as_mut_ptr() as u64, buf.len() as u64];
+    match semihosting_call(SYS_GET_CMDLINE, block.as_mut_ptr() as u64) {
+        0 => {
+            // The host updates the length to that of the returned string.
+            let len = (block[1] as usize).min(buf.len());
+            core::str::from_utf8(&buf[..len]).map_err(|_| Errno(-1))
+        }
+        _ => Err(errno()),
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct HeapInfo {
+    pub heap_base: u64,
+    pub heap_limit: u64,
+    pub stack_base: u64,
+    pub stack_limit: u64,
+}
+
+/// Heap and stack placement suggested by the host. Zero fields mean "not known".
+pub fn heapinfo() -> HeapInfo {
+    let mut info = HeapInfo::default();
+    let block: [u64; 1] = [&mut info as *mut HeapInfo as u64];
+    semihosting_call(SYS_HEAPINFO, block.as_ptr() as u64);
+    info
+}
+
+/// Terminates the emulator, reporting `status` as its exit code.
+pub fn semihosting_exit(status: u64) -> ! {
+    // AArch64 SYS_EXIT takes a parameter block of the stop reason and the exit code.
     #[repr(C)]
     struct QEMUParameterBlock {
         arg0: u64,
@@ -21,18 +256,11 @@ pub fn semihosting_exit(status: u64) {
     }
 
     let block = &QEMUParameterBlock {
-        arg0: 0x20026,
-        arg1: 1,
+        arg0: ADP_STOPPED_APPLICATION_EXIT,
+        arg1: status,
     };
 
-    unsafe {
-        asm!(
-            "hlt #0xF000",
-            in("x0") 0x18,
-            in("x1") block as *const _ as u64,
-            options(nostack)
-        );
-    }
+    semihosting_call(SYS_EXIT, block as *const _ as u64);
 
     loop {
         unsafe { asm!("wfe", options(nomem, nostack)) };
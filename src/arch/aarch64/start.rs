@@ -1,13 +1,27 @@
 #![allow(dead_code)]
 
+use crate::arch::kaslr;
 use crate::kernel_main;
-use aarch64_cpu::{asm::eret, registers::*};
-use core::arch::global_asm;
+use aarch64_cpu::registers::*;
+use core::arch::{asm, global_asm};
 
 global_asm!(include_str!("entry.s"));
 
 #[no_mangle]
-pub unsafe fn _start_cosmos(boot_core_stack_end_exclusive_addr: u64) {
+pub unsafe extern "C" fn _start_cosmos(
+    boot_core_stack_end_exclusive_addr: u64,
+    dtb_addr: u64,
+    load_offset: u64,
+    boot_x1: u64,
+) -> ! {
+    let reentered = boot_x1 == kaslr::REENTRY_MAGIC;
+    kaslr::early_init(load_offset as usize, reentered);
+
+    // KASLR restarts the kernel at EL1, which already runs on this image's stack.
+    if reentered {
+        kernel_main(dtb_addr as usize);
+    }
+
     // Change EL2 to EL1 and jump to kernel_main
 
     // Enable timer counter registers for EL1.
@@ -38,5 +52,7 @@ pub unsafe fn _start_cosmos(boot_core_stack_end_exclusive_addr: u64) {
     // are no plans to ever return to EL2, just re-use the same stack.
     // SP_EL1.set(virt_boot_core_stack_end_exclusive_addr);
     SP_EL1.set(boot_core_stack_end_exclusive_addr);
-    eret();
+
+    // kernel_main() takes the device tree address as its first argument.
+    asm!("eret", in("x0") dtb_addr, options(noreturn));
 }
@@ -1,4 +1,5 @@
 use crate::arch::exception::{irq, state::ExceptionState};
+use arm_gic::gicv3::{IntId, Trigger};
 use log::info;
 
 pub fn test_segfault() {
@@ -24,8 +25,14 @@ pub fn test_sgi() {
 
     // Configure an SGI(Software Generated Interrupt) and then send it to ourself.
     let sgi_id = 3;
-    irq::Interrupt::new(sgi_id, 0x01, 0x00, test_sgi_handler, "test")
-        .register()
-        .enable_irq(true);
+    irq::Interrupt::new(
+        IntId::sgi(sgi_id),
+        Trigger::Edge,
+        0x00,
+        test_sgi_handler,
+        "test",
+    )
+    .register()
+    .enable_irq(true);
     irq::send_sgi(sgi_id);
 }
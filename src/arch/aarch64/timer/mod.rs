@@ -1,29 +1,24 @@
 use super::exception::state::ExceptionState;
-use crate::arch::{drivers::devicetree, exception::irq::Interrupt};
+use crate::arch::{
+    drivers::devicetree,
+    exception::irq::{specifier, Interrupt},
+};
 use aarch64_cpu::{asm::barrier, registers::*};
 use core::time::Duration;
 use log::info;
 use tock_registers::interfaces::ReadWriteable;
 
-pub fn init_irq() {
-    let timer_compatible =
-        core::str::from_utf8(devicetree::get_property("/timer", "compatible").unwrap()).unwrap();
-    if !timer_compatible.contains("armv8-timer") {
-        panic!("Compatible Timer (armv8-timer) Not Found");
-    }
-    let timer_interrupts = devicetree::get_property("/timer", "interrupts").unwrap();
-    const SPLIT_SIZE: usize = core::mem::size_of::<u32>();
-
-    // Order: Secure Timer[0:2], NonSecure Timer[3:5], Virtual Timer[6:8], Hypervisor Timer[9:11]
-    let chunks: &[[u8; SPLIT_SIZE]] = unsafe { timer_interrupts.as_chunks_unchecked() };
-    let timer_irq: Interrupt = Interrupt::from_raw(
-        u32::from_be_bytes(chunks[3]),
-        u32::from_be_bytes(chunks[4]),
-        u32::from_be_bytes(chunks[5]),
-        0x00,
-        timer_handler,
-        "NonSecure Timer",
-    );
+#[link_section = ".init.text"]
+fn init_irq() -> Result<(), &'static str> {
+    let timer = devicetree::Node::find_compatible("arm,armv8-timer")
+        .next()
+        .ok_or("Compatible Timer (armv8-timer) Not Found")?;
+
+    // Without interrupt-names, the binding fixes the order: secure, non-secure, virtual and
+    // hypervisor timer.
+    let (id, trigger) =
+        specifier::parse_by_name(&timer, "phys").or_else(|_| specifier::parse(&timer, 1))?;
+    let timer_irq = Interrupt::new(id, trigger, 0x00, timer_handler, "NonSecure Timer");
 
     timer_irq.register();
 
@@ -31,8 +26,11 @@ pub fn init_irq() {
 
     // Test
     set_timeout_irq_after(CNTFRQ_EL0.get());
+    Ok(())
 }
 
+crate::initcall!(late, init_irq);
+
 fn enable_timer_irq(enable: bool) {
     CNTP_CTL_EL0
         .write(CNTP_CTL_EL0::ENABLE.val(enable as u64) + CNTP_CTL_EL0::IMASK.val(!enable as u64));
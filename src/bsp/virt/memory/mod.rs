@@ -1,13 +1,14 @@
 pub mod symbols;
 
+use crate::arch::memory::maintenance;
 use crate::memory::{
     address_space::{AddressSpace, AssociatedTranslationTable},
-    mmu::page_alloc::kernel_va_allocator,
+    mmu::page_alloc::{kernel_ram_va_allocator, kernel_va_allocator, PageAllocator},
     translation_table::interface::TranslationTable,
     types::*,
 };
 use core::num::NonZeroUsize;
-use spin::RwLock;
+use spin::{Mutex, RwLock};
 use symbols::Section;
 
 pub type KernelTranslationTable =
@@ -40,11 +41,43 @@ pub fn physical_region_of(virt_region: MemoryRegion<Virtual>) -> MemoryRegion<Ph
     )
 }
 
+/// Maps a device's registers into the kernel's MMIO remap window and returns the virtual address
+/// corresponding to `start_addr`.
 pub fn kernel_map_mmio(
     name: &'static str,
     start_addr: Address<Physical>,
     end_addr: Address<Physical>,
-) -> Address<Virtual> {
+) -> Result<Address<Virtual>, &'static str> {
+    map_into(
+        kernel_va_allocator(),
+        start_addr,
+        end_addr,
+        AttributeFields {
+            memory_attributes: MemoryAttributes::Device,
+            access_permissions: AccessPermissions::RW,
+        },
+    )
+}
+
+/// Maps a range of RAM outside the kernel image, e.g. the initrd, into the kernel's RAM remap
+/// window and returns the virtual address corresponding to `start_addr`.
+pub fn kernel_map(
+    name: &'static str,
+    start_addr: Address<Physical>,
+    end_addr: Address<Physical>,
+    attr: AttributeFields,
+) -> Result<Address<Virtual>, &'static str> {
+    map_into(kernel_ram_va_allocator(), start_addr, end_addr, attr)
+}
+
+fn map_into(
+    window: &Mutex<PageAllocator<Virtual>>,
+    start_addr: Address<Physical>,
+    end_addr: Address<Physical>,
+    attr: AttributeFields,
+) -> Result<Address<Virtual>, &'static str> {
+    // Offset of `start_addr` into its page, kept in the returned address.
+    let offset = usize::from(start_addr) & KernelGranule::MASK;
 
     let start_addr = start_addr.align_down_page();
     let end_addr = end_addr.align_up_page();
@@ -52,33 +85,40 @@ pub fn kernel_map_mmio(
     let start_page = PageAddress::from(start_addr);
     let end_page = PageAddress::from(end_addr);
 
-    let offset = usize::from(start_addr) & KernelGranule::MASK;
-
     let phys_region = MemoryRegion::<Physical>::new(start_page, end_page);
     let num_pages = NonZeroUsize::new(usize::from(end_addr - start_addr) >> KernelGranule::SHIFT)
-        .expect("num_pages are not NonZero");
+        .ok_or("Tried to map an empty region")?;
 
-    let virt_region = kernel_va_allocator().lock().alloc(num_pages).unwrap();
+    let virt_region = window.lock().alloc(num_pages)?;
 
-    let _ = KERNEL_TABLES.write().map_at(
-        &virt_region,
-        &phys_region,
-        &AttributeFields {
-            memory_attributes: MemoryAttributes::Device,
-            access_permissions: AccessPermissions::RW,
-        },
-    );
+    KERNEL_TABLES
+        .write()
+        .map_at(&virt_region, &phys_region, &attr)?;
+
+    // Drop stale translations in case the VA range was in use before.
+    maintenance::tlb_flush_va(virt_region.start_addr()..virt_region.end_addr(), None);
 
-    virt_region.start_addr() + Address::<Virtual>::new(offset)
+    Ok(virt_region.start_addr() + Address::<Virtual>::new(offset))
 }
 
-pub fn kernel_sections() -> [Section; 6] {
+pub fn kernel_sections() -> [Section; 8] {
     let device_tree = symbols::device_tree();
     let text = symbols::text();
+    let init = symbols::init();
     let rodata = symbols::rodata();
+    let ro_after_init = symbols::ro_after_init();
     let data = symbols::data();
     let bss = symbols::bss();
     let boot_core_stack = symbols::boot_core_stack();
 
-    [device_tree, text, rodata, data, bss, boot_core_stack]
+    [
+        device_tree,
+        text,
+        init,
+        rodata,
+        ro_after_init,
+        data,
+        bss,
+        boot_core_stack,
+    ]
 }
@@ -14,9 +14,15 @@ extern "Rust" {
     static __text_start_: UnsafeCell<()>;
     static __text_end_: UnsafeCell<()>;
 
+    static __init_start_: UnsafeCell<()>;
+    static __init_end_: UnsafeCell<()>;
+
     static __rodata_start_: UnsafeCell<()>;
     static __rodata_end_: UnsafeCell<()>;
 
+    static __ro_after_init_start_: UnsafeCell<()>;
+    static __ro_after_init_end_: UnsafeCell<()>;
+
     static __data_start_: UnsafeCell<()>;
     static __data_end_: UnsafeCell<()>;
 
@@ -29,6 +35,9 @@ extern "Rust" {
     static __mmio_remap_start_: UnsafeCell<()>;
     static __mmio_remap_end_: UnsafeCell<()>;
 
+    static __ram_remap_start_: UnsafeCell<()>;
+    static __ram_remap_end_: UnsafeCell<()>;
+
     static __PAGE_SIZE_: UnsafeCell<()>;
 }
 
@@ -48,6 +57,29 @@ pub fn kernel_range() -> Range<Address<Physical>> {
     }
 }
 
+/// The part of the kernel that must stay intact while it runs: the device tree area, the image
+/// and the boot core stack.
+pub fn image_range() -> Range<Address<Physical>> {
+    let start_addr: usize = unsafe { __kernel_start_.get() as usize };
+    let end_addr: usize = unsafe { __boot_core_stack_end_.get() as usize };
+
+    Range {
+        start: Address::new(start_addr),
+        end: Address::new(end_addr),
+    }
+}
+
+/// The initialized part of the image as loaded by the boot loader, from `.text` to `.data`.
+pub fn loaded_range() -> Range<Address<Physical>> {
+    let start_addr: usize = unsafe { __text_start_.get() as usize };
+    let end_addr: usize = unsafe { __data_end_.get() as usize };
+
+    Range {
+        start: Address::new(start_addr),
+        end: Address::new(end_addr),
+    }
+}
+
 pub fn device_tree() -> Section {
     let start_addr: usize = unsafe { __device_tree_start_.get() as usize };
     let end_addr: usize = unsafe { __device_tree_end_.get() as usize };
@@ -80,6 +112,23 @@ pub fn text() -> Section {
     }
 }
 
+/// Boot-only code and the initcall tables. Freed by `initcall::free_init`.
+pub fn init() -> Section {
+    let start_addr: usize = unsafe { __init_start_.get() as usize };
+    let end_addr: usize = unsafe { __init_end_.get() as usize };
+    Section {
+        name: ".init",
+        range: Range {
+            start: Address::new(start_addr),
+            end: Address::new(end_addr),
+        },
+        attr: AttributeFields {
+            memory_attributes: MemoryAttributes::CacheableDRAM,
+            access_permissions: AccessPermissions::RX,
+        },
+    }
+}
+
 pub fn rodata() -> Section {
     let start_addr: usize = unsafe { __rodata_start_.get() as usize };
     let end_addr: usize = unsafe { __rodata_end_.get() as usize };
@@ -96,6 +145,23 @@ pub fn rodata() -> Section {
     }
 }
 
+/// Statics placed in `.data.ro_after_init`. Made read-only by `initcall::protect_ro_after_init`.
+pub fn ro_after_init() -> Section {
+    let start_addr: usize = unsafe { __ro_after_init_start_.get() as usize };
+    let end_addr: usize = unsafe { __ro_after_init_end_.get() as usize };
+    Section {
+        name: ".data.ro_after_init",
+        range: Range {
+            start: Address::new(start_addr),
+            end: Address::new(end_addr),
+        },
+        attr: AttributeFields {
+            memory_attributes: MemoryAttributes::ReadOnlyAfterInit,
+            access_permissions: AccessPermissions::RW,
+        },
+    }
+}
+
 pub fn data() -> Section {
     let start_addr: usize = unsafe { __data_start_.get() as usize };
     let end_addr: usize = unsafe { __data_end_.get() as usize };
@@ -153,6 +219,15 @@ pub fn mmio_remap_range() -> Range<Address<Physical>> {
     }
 }
 
+pub fn ram_remap_range() -> Range<Address<Physical>> {
+    let start_addr: usize = unsafe { __ram_remap_start_.get() as usize };
+    let end_addr: usize = unsafe { __ram_remap_end_.get() as usize };
+    Range {
+        start: Address::new(start_addr),
+        end: Address::new(end_addr),
+    }
+}
+
 pub fn page_size() -> MemorySize {
     MemorySize(unsafe { __PAGE_SIZE_.get() as usize })
 }
@@ -1,82 +1,148 @@
-use crate::arch::drivers::devicetree;
+use crate::arch::drivers::devicetree::{self, Node};
 use crate::arch::drivers::pl011::{self, PL011_UART};
 use crate::arch::irq;
 use crate::bsp::memory::symbols::DEVICE_TREE_START;
-use crate::console::register_console;
+use crate::console::serial::{self, LineSettings};
+use crate::console::{emergency, register_console};
+use crate::driver::{self, ProbeError};
+use crate::memory::types::{AccessPermissions, AttributeFields, MemoryAttributes};
+use log::{info, warn, LevelFilter};
 
 pub mod memory;
 
-fn init_device_tree() {
-    devicetree::init(DEVICE_TREE_START);
+/// Sets up the device tree handed over by the boot loader.
+///
+/// The blob is used where the boot loader left it. The area reserved at the start of the kernel
+/// image only holds it when QEMU boots the ELF, and anything else is mapped by
+/// `remap_device_tree`. The blob is not moved into that area: an Image is loaded `text_offset`
+/// past it, so the boot loader is free to have put something else there.
+pub fn init_device_tree(dtb_addr: usize) {
+    // QEMU does not pass the address for ELF kernels but places the blob at the start of RAM.
+    let dtb_addr = match dtb_addr {
+        0 => DEVICE_TREE_START as usize,
+        addr => addr,
+    };
+
+    devicetree::blob_size(dtb_addr).expect("No valid device tree blob found");
+    devicetree::init(dtb_addr);
 }
 
-fn init_uart(real: bool, baud_rate: u32) {
-    fn get_uart_freq() -> u32 {
-        u32::from_be_bytes(
-            devicetree::get_property("/apb-pclk", "clock-frequency")
-                .expect("Property not found")
-                .try_into()
-                .expect("Property is not 4 bytes long"),
-        )
+/// Maps a device tree blob outside the kernel image. Must be called after the MMIO allocator is
+/// set up and before the device tree is accessed with the MMU on.
+#[link_section = ".init.text"]
+fn remap_device_tree() -> Result<(), &'static str> {
+    let blob = devicetree::blob_range();
+    let reserved = memory::symbols::device_tree().range;
+    if blob.start >= reserved.start.value() && blob.end <= reserved.end.value() {
+        return Ok(());
     }
 
-    // Get UART base address and size from device tree
-    let reg = devicetree::get_property("/pl011", "reg").unwrap();
-    let (uart_start, rest) = devicetree::dt_read_u64(&reg); // Typically 0x0900_0000 for PL011 UART
-    let (uart_size, _) = devicetree::dt_read_u64(rest); // Typically 0x1000 for PL011 UART
-
-    let uart_freq = get_uart_freq();
-
-    if real {
-        pl011::init(uart_start, uart_freq, baud_rate);
-    } else {
-        let virt_addr = memory::kernel_map_mmio(
-            "PL011 UART",
-            uart_start.into(),
-            (uart_start + uart_size).into(),
-        );
-        pl011::init(virt_addr.into(), uart_freq, baud_rate);
-    };
-
-    register_console(PL011_UART.get().unwrap());
+    let virt_addr = memory::kernel_map(
+        "Device Tree",
+        blob.start.into(),
+        blob.end.into(),
+        AttributeFields {
+            memory_attributes: MemoryAttributes::CacheableDRAM,
+            access_permissions: AccessPermissions::RO,
+        },
+    )?;
+    devicetree::update_base_address(virt_addr.into());
+    Ok(())
 }
 
-fn init_gicv3(real: bool) {
-    let compat =
-        core::str::from_utf8(devicetree::get_property("/intc", "compatible").unwrap()).unwrap();
-    if !compat.contains("arm,gic-v3") {
-        panic!("Compatible GIC (arm,gic-v3) Not Found");
+crate::initcall!(core, remap_device_tree);
+
+fn probe_pl011(node: Node) -> Result<(), ProbeError> {
+    // The interrupt is registered with the GIC right away.
+    if !irq::is_initialized() {
+        return Err(ProbeError::Defer("interrupt controller not ready"));
     }
 
-    let reg = devicetree::get_property("/intc", "reg").unwrap();
+    // The first clock is the UART reference clock ("uartclk").
+    let clock = node
+        .property("clocks")
+        .and_then(|clocks| Node::find_by_phandle(clocks.cells().next()?))
+        .ok_or(ProbeError::Failed("No UART clock"))?;
+    let uart_freq = clock
+        .property("clock-frequency")
+        .and_then(|frequency| frequency.as_u32())
+        .ok_or(ProbeError::Defer("UART clock not ready"))?;
+
+    // `console=ttyAMA<N>` refers to the UART with alias `serial<N>`.
+    let index = alias_index(&node, "serial").unwrap_or(0);
+    let mut settings = LineSettings::default();
+    if let Some(baud_rate) = node.property("current-speed").and_then(|p| p.as_u32()) {
+        settings.baud_rate = baud_rate;
+    }
+    if let Some(options) = serial::console_options("ttyAMA", index) {
+        match settings.parse_options(options) {
+            Ok(parsed) => settings = parsed,
+            Err(e) => warn!("ttyAMA{}: {}: {}", index, options, e),
+        }
+    }
+
+    // Resolved before any global state changes, so that a bad specifier leaves nothing behind.
+    let (irq_id, irq_trigger) = pl011::irq_spec(&node).map_err(ProbeError::Failed)?;
+
+    // Typically 0x0900_0000, 0x1000
+    let reg = node.reg_at(0).map_err(ProbeError::Failed)?;
+    let virt_addr = memory::kernel_map_mmio(
+        "PL011 UART",
+        reg.address.into(),
+        (reg.address + reg.size).into(),
+    )
+    .map_err(ProbeError::Failed)?;
+    let divisor = pl011::init(virt_addr.into(), uart_freq, settings).map_err(ProbeError::Failed)?;
+    register_console("pl011", PL011_UART.get().unwrap(), LevelFilter::Trace, true)
+        .map_err(ProbeError::Failed)?;
+    emergency::set_sink(virt_addr.into(), pl011::early_putc);
 
+    pl011::init_irq(irq_id, irq_trigger);
+
+    info!("ttyAMA{}: {}, {}", index, settings, divisor);
+    Ok(())
+}
+
+/// `N` of the `<stem>N` alias naming `node`.
+fn alias_index(node: &Node, stem: &str) -> Option<usize> {
+    Node::find("/aliases")?.properties().find_map(|alias| {
+        let index = alias.name.strip_prefix(stem)?.parse().ok()?;
+        (Node::find(alias.as_str()?)? == *node).then_some(index)
+    })
+}
+
+fn probe_gicv3(node: Node) -> Result<(), ProbeError> {
     // GIC Distributor interface (GICD)
-    let (gicd_start, rest) = devicetree::dt_read_u64(&reg);
-    let (gicd_size, rest) = devicetree::dt_read_u64(rest);
-    let gicd_virt_addr: usize =
-        memory::kernel_map_mmio("GICD", gicd_start.into(), (gicd_start + gicd_size).into()).into();
+    let gicd = node.reg_at(0).map_err(ProbeError::Failed)?;
+    let gicd_virt_addr: usize = memory::kernel_map_mmio(
+        "GICD",
+        gicd.address.into(),
+        (gicd.address + gicd.size).into(),
+    )
+    .map_err(ProbeError::Failed)?
+    .into();
 
     // GIC Redistributors (GICR), one range per redistributor region
-    let (gicr_start, rest) = devicetree::dt_read_u64(rest);
-    let (gicr_size, _) = devicetree::dt_read_u64(rest);
-    let gicr_virt_addr: usize =
-        memory::kernel_map_mmio("GICR", gicr_start.into(), (gicr_start + gicr_size).into()).into();
-
-    if real {
-        irq::init_gic(gicd_start as _, gicr_start as _).expect("Failed to initialize GIC");
-    } else {
-        irq::init_gic(gicd_virt_addr as *mut u64, gicr_virt_addr as *mut u64)
-            .expect("Failed to initialize GIC");
-    }
-}
+    let gicr = node.reg_at(1).map_err(ProbeError::Failed)?;
+    let gicr_virt_addr: usize = memory::kernel_map_mmio(
+        "GICR",
+        gicr.address.into(),
+        (gicr.address + gicr.size).into(),
+    )
+    .map_err(ProbeError::Failed)?
+    .into();
 
-pub fn init_drivers(real: bool) {
-    init_device_tree();
-    init_uart(real, 115200);
-    init_gicv3(real);
+    irq::init_gic(gicd_virt_addr as *mut u64, gicr_virt_addr as *mut u64)
+        .map_err(|_| ProbeError::Failed("GIC already initialized"))
 }
 
-pub fn init_irq() {
-    // Initialize Interrupts
-    pl011::init_irq();
+crate::driver!(PL011_DRIVER = "pl011", ["arm,pl011"], probe_pl011);
+crate::driver!(GICV3_DRIVER = "gicv3", ["arm,gic-v3"], probe_gicv3);
+
+#[link_section = ".init.text"]
+fn init_drivers() -> Result<(), &'static str> {
+    driver::probe_all();
+    Ok(())
 }
+
+crate::initcall!(driver, init_drivers);
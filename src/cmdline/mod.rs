@@ -0,0 +1,275 @@
+// Kernel command line
+//
+// The command line is taken from `/chosen/bootargs` or, failing that and only in builds with the
+// `semihosting` feature, from the semihosting host. It is a whitespace separated list of
+// `key=value` pairs or bare `key` flags. Subsystems declare the keys they understand with `param!`,
+// which places a `KernelParam` entry in the `.kernel_params` linker section.
+
+use crate::arch::drivers::devicetree;
+use core::cell::UnsafeCell;
+use log::{info, warn, LevelFilter};
+use spin::{Once, RwLock};
+
+const CMDLINE_MAX: usize = 1024;
+const MAX_REJECTED: usize = 16;
+
+struct Cmdline {
+    buf: [u8; CMDLINE_MAX],
+    len: usize,
+}
+
+impl Cmdline {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+/// An argument that could not be applied, kept until the console is up to report it.
+#[derive(Copy, Clone)]
+struct Rejected {
+    key: &'static str,
+    value: &'static str,
+    // `None` if no subsystem declared the key.
+    reason: Option<&'static str>,
+}
+
+static CMDLINE: Once<Cmdline> = Once::new();
+static REJECTED: RwLock<([Option<Rejected>; MAX_REJECTED], usize)> =
+    RwLock::new(([None; MAX_REJECTED], 0));
+
+/// A value that can be parsed from the command line.
+pub trait ParamValue: Copy + Sized {
+    fn parse(value: &'static str) -> Option<Self>;
+}
+
+/// A typed kernel parameter, declared with `param!`.
+pub struct Param<T: ParamValue> {
+    name: &'static str,
+    value: RwLock<T>,
+}
+
+impl<T: ParamValue> Param<T> {
+    pub const fn new(name: &'static str, default: T) -> Self {
+        Self {
+            name,
+            value: RwLock::new(default),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn get(&self) -> T {
+        *self.value.read()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.value.write() = value;
+    }
+
+    pub fn set_from_str(&self, value: &'static str) -> Result<(), &'static str> {
+        self.set(T::parse(value).ok_or("Invalid value")?);
+        Ok(())
+    }
+}
+
+/// Registry entry placed in `.kernel_params` by `param!`.
+#[repr(C)]
+pub struct KernelParam {
+    pub name: &'static str,
+    pub set: fn(&'static str) -> Result<(), &'static str>,
+}
+
+/// Declares a typed kernel parameter.
+///
+/// ```ignore
+/// param!(pub LOGLEVEL: LevelFilter = "loglevel", LevelFilter::Info);
+/// ```
+#[macro_export]
+macro_rules! param {
+    ($vis:vis $ident:ident: $ty:ty = $name:literal, $default:expr) => {
+        $vis static $ident: $crate::cmdline::Param<$ty> =
+            $crate::cmdline::Param::new($name, $default);
+
+        const _: () = {
+            #[used]
+            #[link_section = ".kernel_params"]
+            static ENTRY: $crate::cmdline::KernelParam = $crate::cmdline::KernelParam {
+                name: $name,
+                set: |value| $ident.set_from_str(value),
+            };
+        };
+    };
+}
+
+fn kernel_params() -> &'static [KernelParam] {
+    extern "Rust" {
+        static __kernel_params_start_: UnsafeCell<()>;
+        static __kernel_params_end_: UnsafeCell<()>;
+    }
+
+    unsafe {
+        let start = __kernel_params_start_.get() as *const KernelParam;
+        let end = __kernel_params_end_.get() as *const KernelParam;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+fn find_param(name: &str) -> Option<&'static KernelParam> {
+    kernel_params().iter().find(|param| param.name == name)
+}
+
+/// Iterates over the `(key, value)` pairs of the command line. Flags have an empty value.
+pub fn args() -> impl Iterator<Item = (&'static str, &'static str)> {
+    get()
+        .split_whitespace()
+        .map(|arg| match arg.split_once('=') {
+            Some((key, value)) => (key, value),
+            None => (arg, ""),
+        })
+}
+
+/// Looks up the raw value of `key`, for keys that are not declared with `param!`.
+pub fn get_arg(key: &str) -> Option<&'static str> {
+    args().find(|(k, _)| *k == key).map(|(_, value)| value)
+}
+
+/// The full command line, empty until `init` ran.
+pub fn get() -> &'static str {
+    CMDLINE.get().map(Cmdline::as_str).unwrap_or("")
+}
+
+fn read_bootargs(buf: &mut [u8]) -> usize {
+    let bootargs = devicetree::Node::find("/chosen")
+        .and_then(|chosen| chosen.property("bootargs"))
+        .and_then(|property| property.as_str())
+        .unwrap_or("");
+
+    if !bootargs.is_empty() {
+        let len = bootargs.len().min(buf.len());
+        buf[..len].copy_from_slice(&bootargs.as_bytes()[..len]);
+        return len;
+    }
+
+    // Asking a host that is not there traps, and this runs before any console exists to say so.
+    // Only builds for QEMU started with -semihosting have the feature, see the Makefile.
+    #[cfg(feature = "semihosting")]
+    {
+        use crate::arch::semihosting;
+
+        // The host reports the program name first, like argv[0].
+        let mut host = [0u8; CMDLINE_MAX];
+        if let Ok(cmdline) = semihosting::get_cmdline(&mut host) {
+            let args = cmdline
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .map(|(_, args)| args.trim())
+                .unwrap_or("");
+            let len = args.len().min(buf.len());
+            buf[..len].copy_from_slice(&args.as_bytes()[..len]);
+            return len;
+        }
+    }
+
+    0
+}
+
+/// Reads the command line and applies every declared parameter found on it.
+///
+/// Must run after the device tree is initialized. Nothing is printed here since the console may
+/// not be up yet, see `report`.
+pub fn init() {
+    CMDLINE.call_once(|| {
+        let mut cmdline = Cmdline {
+            buf: [0; CMDLINE_MAX],
+            len: 0,
+        };
+        cmdline.len = read_bootargs(&mut cmdline.buf);
+        cmdline
+    });
+
+    let mut rejected = REJECTED.write();
+    for (key, value) in args() {
+        let reason = match find_param(key) {
+            Some(param) => match (param.set)(value) {
+                Ok(()) => continue,
+                Err(e) => Some(e),
+            },
+            None => None,
+        };
+
+        let idx = rejected.1;
+        if idx < MAX_REJECTED {
+            rejected.0[idx] = Some(Rejected { key, value, reason });
+            rejected.1 += 1;
+        }
+    }
+}
+
+/// Prints the command line and the arguments that were not applied.
+pub fn report() {
+    info!("Kernel command line: {}", get());
+
+    let rejected = REJECTED.read();
+    for arg in rejected.0[..rejected.1].iter().flatten() {
+        match arg.reason {
+            Some(reason) => warn!(
+                "Invalid value for {}: \"{}\" ({})",
+                arg.key, arg.value, reason
+            ),
+            None => warn!("Unknown kernel parameter \"{}\"", arg.key),
+        }
+    }
+}
+
+impl ParamValue for bool {
+    fn parse(value: &'static str) -> Option<Self> {
+        match value {
+            // A bare flag enables the option.
+            "" | "1" | "y" | "yes" | "on" | "true" => Some(true),
+            "0" | "n" | "no" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_param_value_for_int {
+    ($($ty:ty),*) => {
+        $(
+            impl ParamValue for $ty {
+                fn parse(value: &'static str) -> Option<Self> {
+                    match value.strip_prefix("0x") {
+                        Some(hex) => <$ty>::from_str_radix(hex, 16).ok(),
+                        None => value.parse().ok(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_param_value_for_int!(u8, u16, u32, u64, usize);
+
+impl ParamValue for &'static str {
+    fn parse(value: &'static str) -> Option<Self> {
+        Some(value)
+    }
+}
+
+/// `None` until the parameter is given on the command line.
+impl<T: ParamValue> ParamValue for Option<T> {
+    fn parse(value: &'static str) -> Option<Self> {
+        T::parse(value).map(Some)
+    }
+}
+
+impl ParamValue for LevelFilter {
+    fn parse(value: &'static str) -> Option<Self> {
+        // Accept both level names and their numeric value (0 = off ... 5 = trace).
+        match value.parse::<usize>() {
+            Ok(level) => LevelFilter::iter().nth(level),
+            Err(_) => value.parse().ok(),
+        }
+    }
+}
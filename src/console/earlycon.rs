@@ -0,0 +1,228 @@
+// Early console
+//
+// Until a driver registers the console, output is kept in a buffer. With `earlycon` on the command
+// line, a polled UART writer is set up as soon as the command line is parsed, flushes the buffer
+// and writes everything directly from then on. The UART is mapped before the MMU is turned on, so
+// output keeps working across the switch. When the real console registers, whatever the early
+// console has not shown yet is written to it.
+//
+// Accepted forms:
+//   earlycon                        the UART at /chosen/stdout-path
+//   earlycon=pl011,0x9000000
+//   earlycon=pl011,mmio32,0x9000000
+
+use super::interface;
+use crate::arch::drivers::devicetree::Node;
+use crate::arch::drivers::pl011;
+use crate::bsp;
+use core::fmt;
+use spin::Mutex;
+
+crate::param!(EARLYCON_ARG: Option<&'static str> = "earlycon", None);
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+struct EarlyconDriver {
+    name: &'static str,
+    compatible: &'static str,
+    putc: fn(usize, u8),
+}
+
+static DRIVERS: [EarlyconDriver; 1] = [EarlyconDriver {
+    name: "pl011",
+    compatible: "arm,pl011",
+    putc: pl011::early_putc,
+}];
+
+#[derive(Copy, Clone)]
+struct Earlycon {
+    driver: &'static EarlyconDriver,
+    phys_base: usize,
+    /// The address written to. Switches to `virt_base` once the MMU is on.
+    base: usize,
+    virt_base: Option<usize>,
+}
+
+impl Earlycon {
+    fn write(&self, bytes: &[u8]) {
+        for &c in bytes {
+            (self.driver.putc)(self.base, c);
+        }
+    }
+}
+
+/// Output not shown on any console yet.
+struct Buffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    lost: usize,
+}
+
+impl<const N: usize> fmt::Write for Buffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(N - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        self.lost += s.len() - len;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Buffer<N> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            lost: 0,
+        }
+    }
+
+    /// Hands the buffered output to `write` and empties the buffer.
+    fn drain(&mut self, mut write: impl FnMut(&[u8])) {
+        write(&self.buf[..self.len]);
+        if self.lost > 0 {
+            let mut note = Buffer::<64>::new();
+            let _ = fmt::Write::write_fmt(
+                &mut note,
+                format_args!("[earlycon: {} bytes of output lost]\n", self.lost),
+            );
+            write(&note.buf[..note.len]);
+        }
+        self.len = 0;
+        self.lost = 0;
+    }
+}
+
+static EARLYCON: Mutex<Option<Earlycon>> = Mutex::new(None);
+static BUFFER: Mutex<Buffer<BUFFER_SIZE>> = Mutex::new(Buffer::new());
+
+/// Prints while no console is registered.
+pub(super) fn print(args: fmt::Arguments<'_>) {
+    let mut buffer = BUFFER.lock();
+    let _ = fmt::Write::write_fmt(&mut *buffer, args);
+
+    if let Some(earlycon) = *EARLYCON.lock() {
+        buffer.drain(|bytes| earlycon.write(bytes));
+    }
+}
+
+/// Called when `console` is registered: shows it what was not printed yet and retires the early
+/// console.
+pub(super) fn handover(console: &dyn interface::Console) {
+    EARLYCON.lock().take();
+    BUFFER.lock().drain(|bytes| {
+        // A truncated buffer may end in the middle of a character.
+        for chunk in bytes.utf8_chunks() {
+            let _ = console.write_fmt(format_args!("{}", chunk.valid()));
+        }
+    });
+}
+
+/// The UART named by `/chosen/stdout-path`, either a path or an alias, up to the options after ':'.
+fn stdout_node() -> Option<Node> {
+    let path = Node::find("/chosen")?
+        .property("stdout-path")?
+        .as_str()?
+        .split(':')
+        .next()?;
+    if path.starts_with('/') {
+        return Node::find(path);
+    }
+    Node::find(Node::find("/aliases")?.property(path)?.as_str()?)
+}
+
+fn parse_address(value: &str) -> Option<usize> {
+    usize::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+fn select(option: &str) -> Result<Earlycon, &'static str> {
+    let (driver, phys_base) = if option.is_empty() {
+        let node = stdout_node().ok_or("No usable /chosen/stdout-path")?;
+        let driver = DRIVERS
+            .iter()
+            .find(|driver| node.is_compatible(driver.compatible))
+            .ok_or("stdout-path device is not supported")?;
+        (driver, node.reg_at(0)?.address)
+    } else {
+        let mut fields = option.split(',');
+        let name = fields.next().unwrap_or("");
+        let driver = DRIVERS
+            .iter()
+            .find(|driver| driver.name == name)
+            .ok_or("Unknown earlycon driver")?;
+        // The access width is optional and always 32 bits for the supported UARTs.
+        let address = fields
+            .find(|field| *field != "mmio32")
+            .ok_or("Missing earlycon address")?;
+        (
+            driver,
+            parse_address(address).ok_or("Invalid earlycon address")?,
+        )
+    };
+
+    Ok(Earlycon {
+        driver,
+        phys_base,
+        base: phys_base,
+        virt_base: None,
+    })
+}
+
+/// Starts the early console if `earlycon` was given. Must run after the command line is parsed.
+pub fn init() {
+    let Some(option) = EARLYCON_ARG.get() else {
+        return;
+    };
+
+    match select(option) {
+        Ok(earlycon) => {
+            *EARLYCON.lock() = Some(earlycon);
+            super::emergency::set_sink(earlycon.base, earlycon.driver.putc);
+            println!(
+                "earlycon: {} at {:#x}",
+                earlycon.driver.name, earlycon.phys_base
+            );
+        }
+        // Kept in the buffer for the real console.
+        Err(e) => {
+            println!("earlycon: {}", e);
+        }
+    }
+}
+
+/// Maps the early console's UART. Must run after the MMIO allocator is set up and before the MMU
+/// is turned on.
+pub fn map() {
+    let Some(phys_base) = EARLYCON.lock().map(|earlycon| earlycon.phys_base) else {
+        return;
+    };
+
+    // A single page covers the UART's registers.
+    let virt_base =
+        match bsp::memory::kernel_map_mmio("earlycon", phys_base.into(), (phys_base + 1).into()) {
+            Ok(virt_base) => virt_base,
+            // Kept in the buffer for the real console, like everything printed from here on.
+            Err(e) => {
+                println!("earlycon: {}", e);
+                return;
+            }
+        };
+    if let Some(earlycon) = EARLYCON.lock().as_mut() {
+        earlycon.virt_base = Some(virt_base.into());
+    }
+}
+
+/// Switches to the mapping set up by `map`. Must run right after the MMU is turned on.
+pub fn mmu_enabled() {
+    // Without a mapping the UART is unreachable; go back to buffering.
+    let mut earlycon = EARLYCON.lock();
+    *earlycon = earlycon.and_then(|earlycon| {
+        Some(Earlycon {
+            base: earlycon.virt_base?,
+            ..earlycon
+        })
+    });
+    if let Some(earlycon) = *earlycon {
+        super::emergency::set_sink(earlycon.base, earlycon.driver.putc);
+    }
+}
@@ -0,0 +1,100 @@
+// Emergency console
+//
+// Once the kernel panics or takes a fatal exception, the regular output path cannot be trusted: the
+// panicking code may hold the console or allocator locks, and other cores keep printing. `enter`
+// masks interrupts, claims the emergency path for the calling core and stops the others. From then
+// on all output goes through a raw UART writer that takes no locks and polls the registers
+// directly.
+//
+// UART drivers register the raw writer with `set_sink` as soon as the registers are reachable,
+// since nothing can be mapped any more once the kernel panics.
+
+use crate::arch;
+use crate::arch::irq;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The core on the emergency path, `NO_CPU` until something goes fatally wrong.
+static PANIC_CPU: AtomicUsize = AtomicUsize::new(NO_CPU);
+const NO_CPU: usize = usize::MAX;
+
+/// Base address of the UART and its `putc` as a function pointer, 0 while there is none.
+static SINK_BASE: AtomicUsize = AtomicUsize::new(0);
+static SINK_PUTC: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes `putc(base, c)` the writer for emergency output. `base` must stay mapped.
+pub fn set_sink(base: usize, putc: fn(usize, u8)) {
+    SINK_BASE.store(0, Ordering::Relaxed);
+    SINK_PUTC.store(putc as usize, Ordering::Relaxed);
+    SINK_BASE.store(base, Ordering::Release);
+}
+
+/// Whether output goes through the emergency path.
+pub fn is_active() -> bool {
+    PANIC_CPU.load(Ordering::Relaxed) != NO_CPU
+}
+
+/// Switches to the emergency path. Returns `false` if the calling core is on it already, i.e. it
+/// failed again while reporting a failure. Other cores that get here are stopped.
+pub fn enter() -> bool {
+    irq::irq_disable();
+    irq::fiq_disable();
+
+    let cpu = arch::cpu_id();
+    match PANIC_CPU.compare_exchange(NO_CPU, cpu, Ordering::Acquire, Ordering::Relaxed) {
+        Ok(_) => {
+            irq::stop_other_cpus();
+            true
+        }
+        Err(owner) if owner == cpu => false,
+        // Another core is reporting; it stops this one anyway.
+        Err(_) => stop(),
+    }
+}
+
+/// Parks the calling core for good.
+pub fn stop() -> ! {
+    irq::irq_disable();
+    irq::fiq_disable();
+    loop {
+        arch::halt();
+    }
+}
+
+struct Sink {
+    base: usize,
+    putc: fn(usize, u8),
+}
+
+impl Sink {
+    fn get() -> Option<Self> {
+        let base = SINK_BASE.load(Ordering::Acquire);
+        if base == 0 {
+            return None;
+        }
+        let putc = SINK_PUTC.load(Ordering::Relaxed);
+        // Stored from a `fn(usize, u8)` in `set_sink`.
+        let putc = unsafe { core::mem::transmute::<usize, fn(usize, u8)>(putc) };
+        Some(Self { base, putc })
+    }
+}
+
+impl fmt::Write for Sink {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.bytes() {
+            (self.putc)(self.base, c);
+        }
+        Ok(())
+    }
+}
+
+/// Writes `args` synchronously to the raw UART. Without one, the regular console is used with its
+/// lock broken, which is the best that can be done.
+pub(super) fn print(args: fmt::Arguments<'_>) {
+    match Sink::get() {
+        Some(mut sink) => {
+            let _ = fmt::Write::write_fmt(&mut sink, args);
+        }
+        None => super::print_unlocked(args),
+    }
+}
@@ -0,0 +1,245 @@
+// Kernel log buffer
+//
+// Log records are kept in a fixed ring of slots, so they can be looked at again later, e.g. after
+// a panic. Writers claim a slot with a single atomic increment and never wait: they do not take a
+// lock and do not touch the UART. Each slot carries a sequence number that is cleared while the
+// record is written and set once it is complete, so readers can tell complete, unfinished and
+// overwritten slots apart.
+//
+// Records are printed by `drain`, never by the code that logs them. It runs from the idle loop,
+// between initcalls and before anything is printed directly, so records come out in order with
+// that output. Whoever finds no drain in progress prints everything up to the newest record.
+
+use crate::arch;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+use log::{Level, LevelFilter};
+
+const SLOTS: usize = 256;
+const MESSAGE_SIZE: usize = 192;
+
+#[derive(Copy, Clone)]
+pub struct Record {
+    /// Time since boot.
+    pub timestamp: Duration,
+    pub level: Level,
+    pub cpu: u8,
+    pub module: &'static str,
+    message: [u8; MESSAGE_SIZE],
+    len: u8,
+}
+
+impl Record {
+    /// The formatted message, cut off at `MESSAGE_SIZE` bytes.
+    pub fn message(&self) -> &str {
+        let bytes = &self.message[..self.len as usize];
+        match core::str::from_utf8(bytes) {
+            Ok(message) => message,
+            // Cut in the middle of a character.
+            Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+        }
+    }
+}
+
+impl fmt::Write for Record {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(MESSAGE_SIZE - self.len as usize);
+        let start = self.len as usize;
+        self.message[start..start + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len as u8;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (color, reset) = match super::log::level_color(self.level) {
+            Some(color) => (color, "\x1b[0m"),
+            None => ("", ""),
+        };
+        write!(
+            f,
+            "[ {:>3}.{:06}][cpu{}][{}{}{}] {}",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.cpu,
+            color,
+            self.level,
+            reset,
+            self.message()
+        )
+    }
+}
+
+struct Slot {
+    /// Index of the record in the slot plus one, 0 while it is empty or being written.
+    seq: AtomicUsize,
+    record: UnsafeCell<Record>,
+}
+
+struct Ring {
+    slots: [Slot; SLOTS],
+}
+
+// Access to the records is coordinated through the sequence numbers.
+unsafe impl Sync for Ring {}
+
+const EMPTY_RECORD: Record = Record {
+    timestamp: Duration::ZERO,
+    level: Level::Trace,
+    cpu: 0,
+    module: "",
+    message: [0; MESSAGE_SIZE],
+    len: 0,
+};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: Slot = Slot {
+    seq: AtomicUsize::new(0),
+    record: UnsafeCell::new(EMPTY_RECORD),
+};
+
+static RING: Ring = Ring {
+    slots: [EMPTY_SLOT; SLOTS],
+};
+
+/// Index of the next record to be written.
+static HEAD: AtomicUsize = AtomicUsize::new(0);
+/// Index of the next record to be printed. Only changed by the drain in progress.
+static DRAINED: AtomicUsize = AtomicUsize::new(0);
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Stores a record for `args`. Never blocks.
+pub fn push(level: Level, module: &'static str, args: fmt::Arguments<'_>) {
+    let mut record = Record {
+        timestamp: arch::timer::uptime(),
+        level,
+        cpu: arch::cpu_id() as u8,
+        module,
+        ..EMPTY_RECORD
+    };
+    let _ = fmt::Write::write_fmt(&mut record, args);
+
+    let index = HEAD.fetch_add(1, Ordering::Relaxed);
+    let slot = &RING.slots[index % SLOTS];
+
+    slot.seq.store(0, Ordering::Relaxed);
+    fence(Ordering::Release);
+    unsafe { core::ptr::write_volatile(slot.record.get(), record) };
+    slot.seq.store(index + 1, Ordering::Release);
+}
+
+enum Read {
+    Complete,
+    /// Not written completely yet.
+    Pending,
+    /// Overwritten by a newer record.
+    Lost,
+}
+
+/// Copies record number `index` into `record`.
+fn read(index: usize, record: &mut Record) -> Read {
+    let slot = &RING.slots[index % SLOTS];
+
+    let seq = slot.seq.load(Ordering::Acquire);
+    if seq == 0 || seq < index + 1 {
+        return Read::Pending;
+    }
+    if seq != index + 1 {
+        return Read::Lost;
+    }
+
+    *record = unsafe { core::ptr::read_volatile(slot.record.get()) };
+    fence(Ordering::Acquire);
+    if slot.seq.load(Ordering::Relaxed) != seq {
+        return Read::Lost;
+    }
+    Read::Complete
+}
+
+/// Prints the records logged since the last drain, unless a drain is in progress already.
+pub fn drain() {
+    while !DRAINING.swap(true, Ordering::Acquire) {
+        drain_locked();
+        DRAINING.store(false, Ordering::Release);
+
+        // A record finished while we held `DRAINING` has no one else to print it: its writer's
+        // drain, if any, returned right away. Go again unless it is still being written.
+        let next = DRAINED.load(Ordering::Relaxed);
+        if next == HEAD.load(Ordering::Relaxed) || is_pending(next) {
+            break;
+        }
+    }
+}
+
+/// Whether record number `index` is still being written.
+fn is_pending(index: usize) -> bool {
+    let seq = RING.slots[index % SLOTS].seq.load(Ordering::Acquire);
+    seq == 0 || seq < index + 1
+}
+
+/// Prints the records not printed yet. The caller holds `DRAINING`.
+fn drain_locked() {
+    // Loop so that records logged while printing are not left behind.
+    loop {
+        let head = HEAD.load(Ordering::Relaxed);
+        let mut next = DRAINED.load(Ordering::Relaxed);
+        if next == head {
+            break;
+        }
+
+        if head - next > SLOTS {
+            println!("[klog: {} messages lost]", head - next - SLOTS);
+            next = head - SLOTS;
+        }
+
+        let mut record = EMPTY_RECORD;
+        while next < head {
+            match read(next, &mut record) {
+                Read::Complete => super::print_record(&record),
+                Read::Lost => {
+                    println!("[klog: message lost]");
+                }
+                Read::Pending => break,
+            }
+            next += 1;
+        }
+        DRAINED.store(next, Ordering::Relaxed);
+
+        if next != head {
+            // Still being written, e.g. by the code this interrupted. `drain` checks again.
+            break;
+        }
+    }
+}
+
+/// Prints the records not printed yet even if a drain is in progress, which is never going to
+/// finish after a panic.
+pub fn force_drain() {
+    DRAINING.store(false, Ordering::Release);
+    drain();
+}
+
+/// Calls `f` for every record still in the buffer, oldest first.
+pub fn for_each(mut f: impl FnMut(&Record)) {
+    let head = HEAD.load(Ordering::Relaxed);
+    let mut record = EMPTY_RECORD;
+    for index in head.saturating_sub(SLOTS)..head {
+        if let Read::Complete = read(index, &mut record) {
+            f(&record);
+        }
+    }
+}
+
+/// Prints the records in the buffer at `level` or more severe, and from modules starting with
+/// `module`, which may leave out the crate name.
+pub fn replay(level: LevelFilter, module: &str) {
+    let module = super::log::strip_crate(module);
+    for_each(|record| {
+        if record.level <= level && super::log::strip_crate(record.module).starts_with(module) {
+            println!("{}", record);
+        }
+    });
+}
@@ -1,33 +1,199 @@
+// Logger
+//
+// Records are filtered per target with `env_logger` style directives, e.g.
+// `log=memory::mmu=debug,arch::timer=warn,info`: a bare level sets the default, a bare module
+// enables everything from it, and the longest matching module wins. Module paths may be given with
+// or without the crate name. The filter can be replaced at runtime with `set_filter`.
+
+use super::klog;
 use log::{Level, LevelFilter, Metadata, Record};
+use spin::RwLock;
+
+crate::param!(LOG: Option<&'static str> = "log", None);
+// Overrides the default level of the filter, which otherwise comes from `LOADER_LOG` at compile
+// time.
+crate::param!(LOGLEVEL: Option<LevelFilter> = "loglevel", None);
+// Colours the level of each message.
+crate::param!(LOGCOLOR: bool = "logcolor", false);
+
+const MAX_DIRECTIVES: usize = 16;
+const MODULE_SIZE: usize = 64;
+
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+#[derive(Copy, Clone)]
+struct Directive {
+    module: [u8; MODULE_SIZE],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    fn module(&self) -> &str {
+        // Copied from a &str in `Filter::parse`.
+        core::str::from_utf8(&self.module[..self.len]).unwrap()
+    }
+
+    fn matches(&self, target: &str) -> bool {
+        let module = self.module();
+        match target.strip_prefix(module) {
+            Some(rest) => module.is_empty() || rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        }
+    }
+}
+
+/// A parsed filter specification.
+#[derive(Copy, Clone)]
+pub struct Filter {
+    default: LevelFilter,
+    directives: [Option<Directive>; MAX_DIRECTIVES],
+}
+
+/// `target` without the crate name, as directives are matched against it.
+pub(super) fn strip_crate(target: &str) -> &str {
+    if target == CRATE_PREFIX.trim_end_matches("::") {
+        return "";
+    }
+    target.strip_prefix(CRATE_PREFIX).unwrap_or(target)
+}
+
+fn parse_level(value: &str) -> Option<LevelFilter> {
+    value.parse().ok()
+}
+
+impl Filter {
+    pub const fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            directives: [None; MAX_DIRECTIVES],
+        }
+    }
+
+    /// Parses a comma separated list of `module=level`, `module` and `level` directives.
+    pub fn parse(spec: &str) -> Result<Self, &'static str> {
+        let mut filter = Filter::new(LevelFilter::Info);
+        let mut slots = filter.directives.iter_mut();
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (module, level) = match directive.split_once('=') {
+                Some((module, level)) => (module, parse_level(level).ok_or("Invalid log level")?),
+                None => match parse_level(directive) {
+                    Some(level) => {
+                        filter.default = level;
+                        continue;
+                    }
+                    None => (directive, LevelFilter::Trace),
+                },
+            };
+
+            let module = strip_crate(module);
+            if module.len() > MODULE_SIZE {
+                return Err("Module path too long");
+            }
+            let slot = slots.next().ok_or("Too many log directives")?;
+            let mut entry = Directive {
+                module: [0; MODULE_SIZE],
+                len: module.len(),
+                level,
+            };
+            entry.module[..module.len()].copy_from_slice(module.as_bytes());
+            *slot = Some(entry);
+        }
+        Ok(filter)
+    }
+
+    /// The level for `target`, from the directive with the longest matching module.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let target = strip_crate(target);
+        self.directives
+            .iter()
+            .flatten()
+            .filter(|directive| directive.matches(target))
+            .max_by_key(|directive| directive.len)
+            .map_or(self.default, |directive| directive.level)
+    }
+
+    /// The most verbose level any target gets.
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .flatten()
+            .map(|directive| directive.level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+static FILTER: RwLock<Filter> = RwLock::new(Filter::new(LevelFilter::Info));
+
+/// Replaces the log filter, see `Filter::parse`.
+pub fn set_filter(spec: &str) -> Result<(), &'static str> {
+    apply(Filter::parse(spec)?);
+    Ok(())
+}
+
+fn apply(filter: Filter) {
+    *FILTER.write() = filter;
+    log::set_max_level(filter.max_level());
+}
+
+pub fn set_color(enable: bool) {
+    LOGCOLOR.set(enable);
+}
+
+/// ANSI escape sequence the level of `level` messages is printed in, if colours are on.
+pub fn level_color(level: Level) -> Option<&'static str> {
+    if !LOGCOLOR.get() {
+        return None;
+    }
+    Some(match level {
+        Level::Error => "\x1b[31m",
+        Level::Warn => "\x1b[33m",
+        Level::Info => "\x1b[32m",
+        Level::Debug => "\x1b[34m",
+        Level::Trace => "\x1b[35m",
+    })
+}
 
 struct Logger;
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata<'_>) -> bool {
-        let level = option_env!("LOADER_LOG")
-            .map(|var| var.parse().unwrap())
-            .unwrap_or(Level::Info);
+        // Messages from code interrupting `set_filter` only get the global level.
+        let level = match FILTER.try_read() {
+            Some(filter) => filter.level_for(metadata.target()),
+            None => log::max_level(),
+        };
         metadata.level() <= level
     }
 
     fn log(&self, record: &Record<'_>) {
         if self.enabled(record.metadata()) {
-            let level = record.level();
-            let args = record.args();
-            let time = crate::arch::timer::uptime();
-
-            println!("[ {:>3}.{:06}][{level}] {args}", time.as_secs(), time.subsec_micros());
+            let module = record.module_path_static().unwrap_or("");
+            // Printed later by `klog::drain`, so logging never waits for the UART.
+            klog::push(record.level(), module, *record.args());
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        klog::drain();
+    }
 }
 
-pub fn init() {
+#[link_section = ".init.text"]
+fn init() -> Result<(), &'static str> {
     static LOGGER: Logger = Logger;
-    log::set_logger(&LOGGER).unwrap();
-    let level_filter = option_env!("LOADER_LOG")
-        .map(|var| var.parse().unwrap())
-        .unwrap_or(LevelFilter::Info);
-    log::set_max_level(level_filter);
+    log::set_logger(&LOGGER).map_err(|_| "Logger already set")?;
+
+    // A broken spec still leaves the default level in place, so the error can be seen.
+    let spec = LOG.get().or(option_env!("LOADER_LOG")).unwrap_or("");
+    let parsed = Filter::parse(spec);
+    let mut filter = parsed.unwrap_or(Filter::new(LevelFilter::Info));
+    if let Some(level) = LOGLEVEL.get() {
+        filter.default = level;
+    }
+    apply(filter);
+    parsed.map(|_| ())
 }
+
+crate::initcall!(early, init);
@@ -1,6 +1,13 @@
+use log_crate::LevelFilter;
 use spin::Mutex;
 
+pub mod earlycon;
+pub mod emergency;
+pub mod klog;
 pub mod log;
+pub mod ring;
+pub mod serial;
+pub mod tty;
 
 pub mod interface {
     use core::fmt;
@@ -10,11 +17,16 @@ pub mod interface {
     pub trait Write {
         fn write_char(&self, c: char);
         fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
+        /// Queues as much of `bytes` as fits without waiting and returns how many bytes that was.
+        fn try_write(&self, bytes: &[u8]) -> usize;
         fn flush(&self);
     }
 
+    /// Raw input, as received. Decoding and line editing are left to the TTY.
     pub trait Read {
-        fn read_char(&self) -> char;
+        fn read_byte(&self) -> u8;
+        /// The next received byte, if there is one.
+        fn try_read_byte(&self) -> Option<u8>;
         fn clear_rx(&self);
     }
 
@@ -25,27 +37,201 @@ pub mod interface {
         fn chars_read(&self) -> usize {
             0
         }
+        /// Characters lost because the receiver was not emptied in time.
+        fn overrun_errors(&self) -> usize {
+            0
+        }
+        fn framing_errors(&self) -> usize {
+            0
+        }
+        fn parity_errors(&self) -> usize {
+            0
+        }
+    }
+
+    pub trait Console: Write + Read + Statistics + interrupt::interface::IRQHandler {}
+}
+
+const MAX_CONSOLES: usize = 4;
+
+/// A registered console.
+#[derive(Copy, Clone)]
+pub struct Sink {
+    pub name: &'static str,
+    pub console: &'static (dyn interface::Console + Sync),
+    /// Log records less severe than this are not printed here.
+    pub level: LevelFilter,
+    /// Whether the console can be read from, and so be the input console.
+    pub input: bool,
+}
+
+struct Consoles {
+    sinks: [Option<Sink>; MAX_CONSOLES],
+    /// The console the TTY reads from.
+    input: Option<&'static str>,
+}
+
+impl Consoles {
+    fn find(&mut self, name: &str) -> Option<&mut Option<Sink>> {
+        self.sinks
+            .iter_mut()
+            .find(|sink| sink.is_some_and(|sink| sink.name == name))
     }
+}
+
+static CONSOLES: Mutex<Consoles> = Mutex::new(Consoles {
+    sinks: [None; MAX_CONSOLES],
+    input: None,
+});
+static TTY: tty::Tty = tty::Tty::new();
 
-    pub trait Echo {
-        fn echo(&self);
+/// Adds a console that output is written to from now on. The first console also gets what the
+/// early console buffered, and the first one that takes `input` becomes the input console.
+pub fn register_console(
+    name: &'static str,
+    console: &'static (dyn interface::Console + Sync),
+    level: LevelFilter,
+    input: bool,
+) -> Result<(), &'static str> {
+    let mut consoles = CONSOLES.lock();
+    if consoles.find(name).is_some() {
+        return Err("Console already registered");
+    }
+    let first = consoles.sinks.iter().all(Option::is_none);
+    let slot = consoles
+        .sinks
+        .iter_mut()
+        .find(|sink| sink.is_none())
+        .ok_or("Too many consoles")?;
+    *slot = Some(Sink {
+        name,
+        console,
+        level,
+        input,
+    });
+    let attach = input && consoles.input.is_none();
+    if attach {
+        consoles.input = Some(name);
     }
+    drop(consoles);
 
-    pub trait Console: Write + Read + Statistics + Echo + interrupt::interface::IRQHandler {}
+    if attach {
+        TTY.attach(console);
+    }
+    if first {
+        earlycon::handover(console);
+    }
+    Ok(())
 }
 
-static CONSOLE: Mutex<Option<&'static (dyn interface::Console + Sync)>> = Mutex::new(None);
+/// Removes a console, e.g. when its device goes away. If it was the input console, the next console
+/// that takes input is used instead.
+pub fn unregister_console(name: &str) -> Result<(), &'static str> {
+    let mut consoles = CONSOLES.lock();
+    consoles.find(name).ok_or("No such console")?.take();
+    if consoles.input != Some(name) {
+        return Ok(());
+    }
 
-pub fn register_console(console: &'static (dyn interface::Console + Sync)) {
-    *CONSOLE.lock() = Some(console);
+    let next = consoles
+        .sinks
+        .iter()
+        .flatten()
+        .find(|sink| sink.input)
+        .copied();
+    consoles.input = next.map(|sink| sink.name);
+    drop(consoles);
+    match next {
+        Some(sink) => TTY.attach(sink.console),
+        None => TTY.detach(),
+    }
+    Ok(())
 }
 
-pub fn console() -> &'static dyn interface::Console {
-    CONSOLE.lock().unwrap()
+/// Sets the least severe log level printed on console `name`.
+pub fn set_console_level(name: &str, level: LevelFilter) -> Result<(), &'static str> {
+    let mut consoles = CONSOLES.lock();
+    let sink = consoles.find(name).ok_or("No such console")?;
+    sink.as_mut().unwrap().level = level;
+    Ok(())
+}
+
+/// Makes the TTY read from console `name`.
+pub fn set_input_console(name: &str) -> Result<(), &'static str> {
+    let mut consoles = CONSOLES.lock();
+    let sink = consoles.find(name).ok_or("No such console")?.unwrap();
+    if !sink.input {
+        return Err("Console does not take input");
+    }
+    consoles.input = Some(sink.name);
+    drop(consoles);
+    TTY.attach(sink.console);
+    Ok(())
+}
+
+/// The console the TTY reads from.
+pub fn input_console() -> Option<Sink> {
+    let mut consoles = CONSOLES.lock();
+    let name = consoles.input?;
+    *consoles.find(name)?
+}
+
+/// The registered consoles, in the order they were registered in unless some were removed.
+pub fn consoles() -> impl Iterator<Item = Sink> {
+    CONSOLES.lock().sinks.into_iter().flatten()
+}
+
+/// The TTY on top of the input console.
+pub fn tty() -> &'static tty::Tty {
+    &TTY
 }
 
 #[cfg(target_os = "none")]
 #[doc(hidden)]
 pub fn print(args: core::fmt::Arguments<'_>) {
-    console().write_fmt(args).unwrap();
+    if emergency::is_active() {
+        return emergency::print(args);
+    }
+
+    // Log records from before this come first.
+    klog::drain();
+
+    // Copied out so that the lock is not held while printing.
+    let sinks = CONSOLES.lock().sinks;
+    if sinks.iter().all(Option::is_none) {
+        return earlycon::print(args);
+    }
+    for sink in sinks.iter().flatten() {
+        let _ = sink.console.write_fmt(args);
+    }
+}
+
+/// Prints a log record on the consoles whose level lets it through.
+fn print_record(record: &klog::Record) {
+    if emergency::is_active() {
+        return emergency::print(format_args!("{}\n", record));
+    }
+
+    let sinks = CONSOLES.lock().sinks;
+    if sinks.iter().all(Option::is_none) {
+        return earlycon::print(format_args!("{}\n", record));
+    }
+    for sink in sinks.iter().flatten() {
+        if record.level <= sink.level {
+            let _ = sink.console.write_fmt(format_args!("{}\n", record));
+        }
+    }
+}
+
+/// Prints to the registered consoles even if their lock is held, for the emergency path.
+fn print_unlocked(args: core::fmt::Arguments<'_>) {
+    // The holder is stopped or is the panicking code itself, so it never releases the lock.
+    if CONSOLES.is_locked() {
+        unsafe { CONSOLES.force_unlock() };
+    }
+    // Without a console, buffered output would never be seen.
+    let sinks = CONSOLES.lock().sinks;
+    for sink in sinks.iter().flatten() {
+        let _ = sink.console.write_fmt(args);
+    }
 }
@@ -0,0 +1,70 @@
+// Ring buffer
+//
+// Fixed size FIFO used by console drivers and the TTY to queue input and output between the caller
+// and interrupt handlers. It does no locking of its own; the owner serializes access.
+
+use core::mem::MaybeUninit;
+
+pub struct RingBuffer<T: Copy, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    /// Index of the oldest element.
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { MaybeUninit::uninit() }; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Appends `value`. Returns `false` if the buffer is full.
+    pub fn push(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buf[(self.head + self.len) % N] = MaybeUninit::new(value);
+        self.len += 1;
+        true
+    }
+
+    /// Removes and returns the oldest element.
+    pub fn pop(&mut self) -> Option<T> {
+        let value = self.peek()?;
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// The oldest element, without removing it.
+    pub fn peek(&self) -> Option<T> {
+        // Every slot from `head` on for `len` elements was written by `push`.
+        (!self.is_empty()).then(|| unsafe { self.buf[self.head].assume_init() })
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,142 @@
+// Serial line settings
+//
+// Settings come from the device tree's `current-speed` and from `console=<device>,<options>` on the
+// command line, with options in the Linux format `<baud><parity><bits><flow>`, e.g. `115200n8` or
+// `38400e7r`. Parity is `n`, `o` or `e`, `r` enables RTS/CTS flow control. Anything left out keeps
+// its default of 8N1 without flow control.
+
+use crate::cmdline::ParamValue;
+use core::fmt::Display;
+
+crate::param!(CONSOLE_ARG: Option<ConsoleArg> = "console", None);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LineSettings {
+    pub baud_rate: u32,
+    /// 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// RTS/CTS hardware flow control.
+    pub flow_control: bool,
+}
+
+impl LineSettings {
+    pub const fn new(baud_rate: u32) -> Self {
+        Self {
+            baud_rate,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: false,
+        }
+    }
+
+    /// Applies `<baud><parity><bits><flow>` options on top of these settings.
+    pub fn parse_options(mut self, options: &str) -> Result<Self, &'static str> {
+        let digits = options
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(options.len());
+        let (baud_rate, mut rest) = options.split_at(digits);
+        if !baud_rate.is_empty() {
+            self.baud_rate = baud_rate.parse().map_err(|_| "Invalid baud rate")?;
+        }
+        if self.baud_rate == 0 {
+            return Err("Invalid baud rate");
+        }
+
+        let mut chars = rest.chars();
+        if let Some(parity) = chars.next() {
+            self.parity = match parity {
+                'n' => Parity::None,
+                'o' => Parity::Odd,
+                'e' => Parity::Even,
+                _ => return Err("Invalid parity"),
+            };
+            rest = chars.as_str();
+        }
+
+        let mut chars = rest.chars();
+        if let Some(bits) = chars.next() {
+            self.data_bits = match bits.to_digit(10) {
+                Some(bits @ 5..=8) => bits as u8,
+                _ => return Err("Invalid number of data bits"),
+            };
+            rest = chars.as_str();
+        }
+
+        self.flow_control = match rest {
+            "" => false,
+            "r" => true,
+            _ => return Err("Invalid flow control"),
+        };
+        Ok(self)
+    }
+}
+
+impl Default for LineSettings {
+    fn default() -> Self {
+        Self::new(115200)
+    }
+}
+
+impl Display for LineSettings {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        write!(
+            f,
+            "{} {}{}{}",
+            self.baud_rate, self.data_bits, parity, stop_bits
+        )?;
+        if self.flow_control {
+            write!(f, " RTS/CTS")?;
+        }
+        Ok(())
+    }
+}
+
+/// `console=<device>[,<options>]`
+#[derive(Copy, Clone)]
+pub struct ConsoleArg {
+    pub device: &'static str,
+    pub options: Option<&'static str>,
+}
+
+impl ParamValue for ConsoleArg {
+    fn parse(value: &'static str) -> Option<Self> {
+        let (device, options) = match value.split_once(',') {
+            Some((device, options)) => (device, Some(options)),
+            None => (value, None),
+        };
+        (!device.is_empty()).then_some(Self { device, options })
+    }
+}
+
+/// The options given with `console=` for device `index` of a driver, e.g. `ttyAMA` and 0 for
+/// `console=ttyAMA0,115200n8`.
+pub fn console_options(prefix: &str, index: usize) -> Option<&'static str> {
+    let arg = CONSOLE_ARG.get()?;
+    let device_index = arg.device.strip_prefix(prefix)?.parse::<usize>().ok()?;
+    (device_index == index).then_some(arg.options).flatten()
+}
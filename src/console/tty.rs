@@ -0,0 +1,395 @@
+// TTY
+//
+// Sits between a console driver, which only moves bytes, and whoever reads the console. Input is
+// decoded from UTF-8 and, depending on the mode, edited a line at a time (canonical mode) or
+// passed on as it arrives (raw mode). The result is queued as `Input` events that readers poll
+// with `read`. `receive` moves what the driver has received into the queue; drivers call it from
+// their receive interrupt.
+//
+// In canonical mode, Backspace or DEL erases the last character, Ctrl-U the whole line, and the
+// line is only handed to readers once Enter is pressed. Cursor keys are queued right away in
+// either mode. With signal characters on, Ctrl-C discards the line and Ctrl-D on an empty line
+// ends input.
+
+use super::interface;
+use super::ring::RingBuffer;
+use crate::arch::irq;
+use spin::Mutex;
+
+const LINE_SIZE: usize = 256;
+const QUEUE_SIZE: usize = 512;
+
+const CTRL_C: char = '\x03';
+const CTRL_D: char = '\x04';
+const BACKSPACE: char = '\x08';
+const CTRL_U: char = '\x15';
+const ESC: char = '\x1b';
+const DEL: char = '\x7f';
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Delete,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Input {
+    Char(char),
+    /// A cursor key, with `Mode::decode_keys`.
+    Key(Key),
+    /// Ctrl-C, with `Mode::signals`.
+    Interrupt,
+    /// Ctrl-D on an empty line, with `Mode::signals`.
+    Eof,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mode {
+    /// Edit input a line at a time and hand it over on Enter.
+    pub canonical: bool,
+    /// Write typed characters back to the device.
+    pub echo: bool,
+    /// Turn Ctrl-C and Ctrl-D into `Input::Interrupt` and `Input::Eof`.
+    pub signals: bool,
+    /// Decode ANSI escape sequences for cursor keys into `Input::Key`. A lone Escape is held back
+    /// until the next character shows it does not start a sequence.
+    pub decode_keys: bool,
+    /// Map carriage return to newline, as terminals send CR for Enter.
+    pub cr_to_nl: bool,
+}
+
+impl Mode {
+    pub const CANONICAL: Mode = Mode {
+        canonical: true,
+        echo: true,
+        signals: true,
+        decode_keys: false,
+        cr_to_nl: true,
+    };
+
+    /// Every byte as it is, without echo.
+    pub const RAW: Mode = Mode {
+        canonical: false,
+        echo: false,
+        signals: false,
+        decode_keys: false,
+        cr_to_nl: false,
+    };
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::CANONICAL
+    }
+}
+
+/// Assembles characters from UTF-8 bytes. Malformed input comes out as U+FFFD.
+struct Utf8Decoder {
+    buf: [u8; 4],
+    len: usize,
+    need: usize,
+}
+
+impl Utf8Decoder {
+    const fn new() -> Self {
+        Self {
+            buf: [0; 4],
+            len: 0,
+            need: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8, mut emit: impl FnMut(char)) {
+        if self.len > 0 {
+            if byte & 0xc0 == 0x80 {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                if self.len == self.need {
+                    // Also rejects overlong forms and surrogates.
+                    let c = core::str::from_utf8(&self.buf[..self.len])
+                        .ok()
+                        .and_then(|s| s.chars().next());
+                    emit(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                    self.len = 0;
+                }
+                return;
+            }
+            // The sequence was cut short; `byte` starts the next one.
+            self.len = 0;
+            emit(char::REPLACEMENT_CHARACTER);
+        }
+
+        let need = match byte {
+            0x00..=0x7f => return emit(byte as char),
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return emit(char::REPLACEMENT_CHARACTER),
+        };
+        self.buf[0] = byte;
+        self.len = 1;
+        self.need = need;
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Escape {
+    None,
+    /// After ESC.
+    Esc,
+    /// After `ESC [` and an optional numeric parameter.
+    Csi(Option<u32>),
+}
+
+struct State {
+    mode: Mode,
+    decoder: Utf8Decoder,
+    escape: Escape,
+    /// The line being edited in canonical mode.
+    line: [char; LINE_SIZE],
+    line_len: usize,
+    queue: RingBuffer<Input, QUEUE_SIZE>,
+    /// Input thrown away because the queue was full.
+    dropped: usize,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            mode: Mode::CANONICAL,
+            decoder: Utf8Decoder::new(),
+            escape: Escape::None,
+            line: ['\0'; LINE_SIZE],
+            line_len: 0,
+            queue: RingBuffer::new(),
+            dropped: 0,
+        }
+    }
+
+    fn queue(&mut self, input: Input) {
+        if !self.queue.push(input) {
+            self.dropped += 1;
+        }
+    }
+
+    /// Hands the edited line to readers.
+    fn commit_line(&mut self) {
+        for index in 0..self.line_len {
+            self.queue(Input::Char(self.line[index]));
+        }
+        self.line_len = 0;
+    }
+
+    fn echo(&self, device: &dyn interface::Console, s: &str) {
+        if self.mode.echo {
+            for c in s.chars() {
+                device.write_char(c);
+            }
+        }
+    }
+
+    fn echo_char(&self, device: &dyn interface::Console, c: char) {
+        let mut buf = [0u8; 4];
+        self.echo(device, c.encode_utf8(&mut buf));
+    }
+
+    fn erase(&mut self, device: &dyn interface::Console) -> bool {
+        if self.line_len == 0 {
+            return false;
+        }
+        self.line_len -= 1;
+        self.echo(device, "\x08 \x08");
+        true
+    }
+
+    /// Decodes cursor keys. Returns `Some(true)` if `c` was taken as part of an escape sequence, and
+    /// `None` if a held back Escape has to be passed on before `c`.
+    fn escape(&mut self, c: char) -> Option<bool> {
+        match self.escape {
+            Escape::None if self.mode.decode_keys && c == ESC => {
+                self.escape = Escape::Esc;
+                Some(true)
+            }
+            Escape::None => Some(false),
+            Escape::Esc if c == '[' => {
+                self.escape = Escape::Csi(None);
+                Some(true)
+            }
+            // Not a sequence after all; the Escape is passed on before `c`.
+            Escape::Esc => {
+                self.escape = Escape::None;
+                None
+            }
+            Escape::Csi(param) => {
+                self.escape = Escape::None;
+                let key = match (c, param) {
+                    ('0'..='9', _) => {
+                        let digit = c.to_digit(10).unwrap();
+                        let param = param.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                        self.escape = Escape::Csi(Some(param));
+                        return Some(true);
+                    }
+                    ('A', _) => Key::Up,
+                    ('B', _) => Key::Down,
+                    ('C', _) => Key::Right,
+                    ('D', _) => Key::Left,
+                    ('H', _) | ('~', Some(1 | 7)) => Key::Home,
+                    ('F', _) | ('~', Some(4 | 8)) => Key::End,
+                    ('~', Some(3)) => Key::Delete,
+                    // Other sequences are dropped.
+                    _ => return Some(true),
+                };
+                self.queue(Input::Key(key));
+                Some(true)
+            }
+        }
+    }
+
+    fn receive_char(&mut self, device: &dyn interface::Console, c: char) {
+        match self.escape(c) {
+            Some(true) => {}
+            Some(false) => self.process(device, c),
+            None => {
+                self.process(device, ESC);
+                // `c` may start another sequence.
+                self.receive_char(device, c);
+            }
+        }
+    }
+
+    fn process(&mut self, device: &dyn interface::Console, c: char) {
+        let c = match c {
+            '\r' if self.mode.cr_to_nl => '\n',
+            c => c,
+        };
+
+        if self.mode.signals {
+            match c {
+                CTRL_C => {
+                    self.line_len = 0;
+                    self.echo(device, "^C\n");
+                    return self.queue(Input::Interrupt);
+                }
+                CTRL_D if self.mode.canonical && self.line_len > 0 => {
+                    // Hands over the line without a newline, like POSIX terminals.
+                    return self.commit_line();
+                }
+                CTRL_D => return self.queue(Input::Eof),
+                _ => {}
+            }
+        }
+
+        if !self.mode.canonical {
+            self.echo_char(device, c);
+            return self.queue(Input::Char(c));
+        }
+
+        match c {
+            BACKSPACE | DEL => {
+                self.erase(device);
+            }
+            CTRL_U => while self.erase(device) {},
+            '\n' => {
+                self.echo(device, "\n");
+                self.commit_line();
+                self.queue(Input::Char('\n'));
+            }
+            c if self.line_len < LINE_SIZE => {
+                self.line[self.line_len] = c;
+                self.line_len += 1;
+                self.echo_char(device, c);
+            }
+            // The line is full; only editing and Enter are taken.
+            _ => {}
+        }
+    }
+}
+
+pub struct Tty {
+    device: Mutex<Option<&'static (dyn interface::Console + Sync)>>,
+    state: Mutex<State>,
+}
+
+impl Tty {
+    pub const fn new() -> Self {
+        Self {
+            device: Mutex::new(None),
+            state: Mutex::new(State::new()),
+        }
+    }
+
+    /// Runs `f` with interrupts masked, as `receive` runs in the receive interrupt.
+    fn with_state<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        irq::exec_with_irq_disabled(|| f(&mut self.state.lock()))
+    }
+
+    /// Reads from `device` from now on. Input still queued is discarded.
+    pub fn attach(&self, device: &'static (dyn interface::Console + Sync)) {
+        *self.device.lock() = Some(device);
+        self.with_state(|state| {
+            let mode = state.mode;
+            *state = State::new();
+            state.mode = mode;
+        });
+    }
+
+    /// Stops reading input, e.g. when the device goes away.
+    pub fn detach(&self) {
+        *self.device.lock() = None;
+    }
+
+    /// Processes everything the device has received.
+    pub fn receive(&self) {
+        let Some(device) = *self.device.lock() else {
+            return;
+        };
+        self.with_state(|state| {
+            while let Some(byte) = device.try_read_byte() {
+                let mut decoded = ['\0'; 2];
+                let mut count = 0;
+                state.decoder.push(byte, |c| {
+                    decoded[count] = c;
+                    count += 1;
+                });
+                for &c in &decoded[..count] {
+                    state.receive_char(device, c);
+                }
+            }
+        });
+    }
+
+    /// The next input, if there is any.
+    pub fn read(&self) -> Option<Input> {
+        self.with_state(|state| state.queue.pop())
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.with_state(|state| state.mode)
+    }
+
+    /// Switches to `mode`. A line being edited is handed over as it is when leaving canonical mode.
+    pub fn set_mode(&self, mode: Mode) {
+        self.with_state(|state| {
+            if state.mode.canonical && !mode.canonical {
+                state.commit_line();
+            }
+            state.mode = mode;
+        });
+    }
+
+    /// Input thrown away because nobody read it in time.
+    pub fn dropped(&self) -> usize {
+        self.with_state(|state| state.dropped)
+    }
+}
+
+impl Default for Tty {
+    fn default() -> Self {
+        Self::new()
+    }
+}
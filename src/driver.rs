@@ -1,3 +1,16 @@
+// Device drivers
+//
+// Drivers declare the `compatible` strings they handle with driver!. At boot, `probe_all` walks the
+// device tree and binds every enabled node to the driver matching its most specific compatible
+// string. A probe that depends on something not set up yet (an interrupt controller, a clock)
+// returns `ProbeError::Defer` and is retried after the other devices have been probed.
+
+use crate::arch::drivers::devicetree::Node;
+use crate::sync::spinlock::Spinlock;
+use core::cell::UnsafeCell;
+use core::fmt::Display;
+use log::info;
+
 pub mod interface {
     pub trait DeviceDriver {
         fn init(&self) -> Result<(), &'static str> {
@@ -5,3 +18,154 @@ pub mod interface {
         }
     }
 }
+
+#[derive(Copy, Clone, Debug)]
+pub enum ProbeError {
+    /// A dependency is not ready yet; probe again later.
+    Defer(&'static str),
+    Failed(&'static str),
+}
+
+pub struct Driver {
+    pub name: &'static str,
+    pub compatible: &'static [&'static str],
+    pub probe: fn(Node) -> Result<(), ProbeError>,
+}
+
+/// Registers a driver, e.g.
+/// `driver!(PL011_DRIVER = "pl011", ["arm,pl011"], probe);`
+#[macro_export]
+macro_rules! driver {
+    ($ident:ident = $name:literal, [$($compatible:literal),+ $(,)?], $probe:expr) => {
+        #[used]
+        #[link_section = ".drivers"]
+        static $ident: $crate::driver::Driver = $crate::driver::Driver {
+            name: $name,
+            compatible: &[$($compatible),+],
+            probe: $probe,
+        };
+    };
+}
+
+fn drivers() -> &'static [Driver] {
+    extern "Rust" {
+        static __drivers_start_: UnsafeCell<()>;
+        static __drivers_end_: UnsafeCell<()>;
+    }
+
+    unsafe {
+        let start = __drivers_start_.get() as *const Driver;
+        let end = __drivers_end_.get() as *const Driver;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+/// The driver for `node`, preferring the first, most specific, of its compatible strings.
+fn find_driver(node: &Node) -> Option<&'static Driver> {
+    node.compatible().find_map(|compatible| {
+        drivers()
+            .iter()
+            .find(|driver| driver.compatible.contains(&compatible))
+    })
+}
+
+#[derive(Copy, Clone)]
+pub enum DeviceState {
+    Pending,
+    Bound,
+    Deferred(&'static str),
+    Failed(&'static str),
+}
+
+impl Display for DeviceState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DeviceState::Pending => write!(f, "pending"),
+            DeviceState::Bound => write!(f, "bound"),
+            DeviceState::Deferred(reason) => write!(f, "deferred ({})", reason),
+            DeviceState::Failed(reason) => write!(f, "failed ({})", reason),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Device {
+    pub node: Node,
+    pub driver: &'static Driver,
+    pub state: DeviceState,
+}
+
+const MAX_DEVICES: usize = 64;
+static DEVICES: Spinlock<[Option<Device>; MAX_DEVICES]> = Spinlock::new([None; MAX_DEVICES]);
+
+fn collect_devices() {
+    let mut devices = DEVICES.lock();
+    let mut slots = devices.iter_mut();
+
+    for node in Node::all().filter(|node| node.is_enabled()) {
+        let Some(driver) = find_driver(&node) else {
+            continue;
+        };
+        // Probing happens before there is a console to complain on.
+        let Some(slot) = slots.next() else {
+            break;
+        };
+        *slot = Some(Device {
+            node,
+            driver,
+            state: DeviceState::Pending,
+        });
+    }
+}
+
+/// Probes every pending or deferred device once. Returns whether any was bound.
+fn probe_pass() -> bool {
+    let mut progress = false;
+
+    for index in 0..MAX_DEVICES {
+        // The lock is dropped while probing, as probes may look at other devices.
+        let device = match DEVICES.lock()[index] {
+            Some(device) => device,
+            None => break,
+        };
+        if !matches!(
+            device.state,
+            DeviceState::Pending | DeviceState::Deferred(_)
+        ) {
+            continue;
+        }
+
+        let state = match (device.driver.probe)(device.node) {
+            Ok(()) => DeviceState::Bound,
+            Err(ProbeError::Defer(reason)) => DeviceState::Deferred(reason),
+            Err(ProbeError::Failed(reason)) => DeviceState::Failed(reason),
+        };
+        progress |= matches!(state, DeviceState::Bound);
+        DEVICES.lock()[index].as_mut().unwrap().state = state;
+    }
+    progress
+}
+
+/// Binds drivers to the devices in the device tree. Deferred devices are retried until a pass
+/// binds nothing new; those left over stay deferred.
+pub fn probe_all() {
+    collect_devices();
+    while probe_pass() {}
+}
+
+/// Calls `f` for every device that has a driver.
+pub fn for_each_device(f: impl FnMut(&Device)) {
+    let devices = *DEVICES.lock();
+    devices.iter().flatten().for_each(f);
+}
+
+pub fn print_devices() {
+    for_each_device(|device| {
+        info!(
+            "      {: <24} {: <8} {}",
+            device.node.name(),
+            device.driver.name,
+            device.state
+        );
+    });
+}
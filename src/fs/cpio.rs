@@ -0,0 +1,121 @@
+// cpio archives in the "newc" format (magic 070701, or 070702 with checksums)
+//
+// Every entry is a 110 byte ASCII header of hex fields, followed by the NUL-terminated name and
+// the file data, both padded to 4 bytes. The archive ends with an entry named "TRAILER!!!".
+
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFLNK: u32 = 0o120000;
+
+#[derive(Copy, Clone)]
+pub struct Entry {
+    /// Path inside the archive, without a leading "./" or "/". The root directory is "".
+    pub name: &'static str,
+    pub ino: u32,
+    pub mode: u32,
+    pub mtime: u32,
+    /// File contents, or the target of a symbolic link.
+    pub data: &'static [u8],
+}
+
+pub struct Entries {
+    archive: &'static [u8],
+    offset: usize,
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match parse_entry(self.archive, self.offset) {
+            Ok(Some((entry, next))) => {
+                self.offset = next;
+                Some(Ok(entry))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                // Stop after the first error.
+                self.offset = self.archive.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterates over the entries of `archive`, up to the trailer.
+pub fn entries(archive: &'static [u8]) -> Entries {
+    Entries { archive, offset: 0 }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn hex_field(header: &[u8], index: usize) -> Result<u32, &'static str> {
+    // Fields follow the 6 byte magic, 8 hex digits each.
+    let start = 6 + index * 8;
+    let digits =
+        core::str::from_utf8(&header[start..start + 8]).map_err(|_| "Invalid cpio header")?;
+    u32::from_str_radix(digits, 16).map_err(|_| "Invalid cpio header")
+}
+
+fn parse_entry(
+    archive: &'static [u8],
+    offset: usize,
+) -> Result<Option<(Entry, usize)>, &'static str> {
+    if offset >= archive.len() {
+        return Err("cpio archive is missing its trailer");
+    }
+
+    let header = archive
+        .get(offset..offset + HEADER_SIZE)
+        .ok_or("Truncated cpio header")?;
+    if &header[..6] != b"070701" && &header[..6] != b"070702" {
+        return Err("Not a newc cpio archive");
+    }
+
+    let ino = hex_field(header, 0)?;
+    let mode = hex_field(header, 1)?;
+    let mtime = hex_field(header, 5)?;
+    let filesize = hex_field(header, 6)? as usize;
+    let namesize = hex_field(header, 11)? as usize;
+
+    let name_start = offset + HEADER_SIZE;
+    let name = archive
+        .get(name_start..name_start + namesize)
+        .ok_or("Truncated cpio name")?;
+    let name = core::str::from_utf8(name)
+        .map_err(|_| "Invalid cpio name")?
+        .trim_end_matches('\0');
+    if name == TRAILER {
+        return Ok(None);
+    }
+
+    let data_start = align4(name_start + namesize);
+    let data = archive
+        .get(data_start..data_start + filesize)
+        .ok_or("Truncated cpio data")?;
+
+    let entry = Entry {
+        name: normalize(name),
+        ino,
+        mode,
+        mtime,
+        data,
+    };
+    Ok(Some((entry, align4(data_start + filesize))))
+}
+
+/// Strips "./", leading and trailing slashes, so that paths compare equal to archive names.
+pub fn normalize(path: &str) -> &str {
+    let path = path.trim_start_matches("./").trim_matches('/');
+    if path == "." {
+        ""
+    } else {
+        path
+    }
+}
@@ -0,0 +1,155 @@
+// Initial RAM file system
+//
+// The boot loader places a newc cpio archive in RAM and passes its location in
+// `/chosen/linux,initrd-{start,end}`, e.g. with `qemu -initrd`. The archive is mapped read-only
+// and files are served straight out of it.
+
+use super::{cpio, interface, DirEntry, FileType, Metadata};
+use crate::arch::drivers::devicetree;
+use crate::bsp;
+use crate::memory::types::*;
+use crate::sync::spinlock::RawSpinlock;
+use generic_once_cell::OnceCell;
+use log::info;
+
+static INITRAMFS: OnceCell<RawSpinlock, Initramfs> = OnceCell::new();
+
+pub struct Initramfs {
+    archive: &'static [u8],
+}
+
+fn file_type(mode: u32) -> FileType {
+    match mode & cpio::S_IFMT {
+        cpio::S_IFREG => FileType::File,
+        cpio::S_IFDIR => FileType::Directory,
+        cpio::S_IFLNK => FileType::Symlink,
+        _ => FileType::Other,
+    }
+}
+
+/// The name of `entry` relative to `dir`, if it lies below it.
+fn relative_to<'a>(entry: &'a str, dir: &str) -> Option<&'a str> {
+    if dir.is_empty() {
+        return Some(entry);
+    }
+    entry.strip_prefix(dir)?.strip_prefix('/')
+}
+
+impl Initramfs {
+    /// Checks that `archive` is a well-formed cpio archive.
+    pub fn new(archive: &'static [u8]) -> Result<Self, &'static str> {
+        cpio::entries(archive).try_for_each(|entry| entry.map(|_| ()))?;
+        Ok(Self { archive })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = cpio::Entry> {
+        // Errors were ruled out by new().
+        cpio::entries(self.archive).flatten()
+    }
+
+    fn find(&self, path: &str) -> Option<cpio::Entry> {
+        self.entries().find(|entry| entry.name == path)
+    }
+}
+
+impl interface::FileSystem for Initramfs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata, &'static str> {
+        let path = cpio::normalize(path);
+
+        if let Some(entry) = self.find(path) {
+            return Ok(Metadata {
+                file_type: file_type(entry.mode),
+                size: entry.data.len(),
+                permissions: entry.mode & 0o7777,
+                mtime: entry.mtime,
+            });
+        }
+
+        // Archives do not always list the directories their files live in.
+        if path.is_empty()
+            || self
+                .entries()
+                .any(|entry| relative_to(entry.name, path).is_some())
+        {
+            return Ok(Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+                permissions: 0o755,
+                mtime: 0,
+            });
+        }
+
+        Err("No such file or directory")
+    }
+
+    fn read(&self, path: &str) -> Result<&'static [u8], &'static str> {
+        let entry = self
+            .find(cpio::normalize(path))
+            .ok_or("No such file or directory")?;
+
+        match file_type(entry.mode) {
+            FileType::File => Ok(entry.data),
+            FileType::Directory => Err("Is a directory"),
+            _ => Err("Not a regular file"),
+        }
+    }
+
+    fn read_dir(&self, path: &str, f: &mut dyn FnMut(DirEntry)) -> Result<(), &'static str> {
+        let path = cpio::normalize(path);
+        if self.metadata(path)?.file_type != FileType::Directory {
+            return Err("Not a directory");
+        }
+
+        for entry in self.entries() {
+            match relative_to(entry.name, path) {
+                Some(name) if !name.is_empty() && !name.contains('/') => f(DirEntry {
+                    name,
+                    file_type: file_type(entry.mode),
+                }),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the initrd, checks it and mounts it as the root file system.
+#[link_section = ".init.text"]
+fn init() -> Result<(), &'static str> {
+    let Some(initrd) = devicetree::initrd_range() else {
+        info!("initramfs: no initrd passed");
+        return Ok(());
+    };
+
+    let virt_addr = bsp::memory::kernel_map(
+        "initrd",
+        initrd.start.into(),
+        initrd.end.into(),
+        AttributeFields {
+            memory_attributes: MemoryAttributes::CacheableDRAM,
+            access_permissions: AccessPermissions::RO,
+        },
+    )?;
+    let archive =
+        unsafe { core::slice::from_raw_parts(usize::from(virt_addr) as *const u8, initrd.len()) };
+
+    INITRAMFS
+        .set(Initramfs::new(archive)?)
+        .map_err(|_| "initramfs already initialized")?;
+    let initramfs = INITRAMFS.get().unwrap();
+    super::mount_root(initramfs);
+
+    info!(
+        "initramfs: {} entries, {} bytes at {:#x}",
+        initramfs.entries().count(),
+        initrd.len(),
+        initrd.start
+    );
+    Ok(())
+}
+
+crate::initcall!(late, init);
@@ -0,0 +1,61 @@
+// File systems
+//
+// There is no block device support yet. The only file system is the initramfs, a read-only view of
+// the cpio archive the boot loader passed as initrd, mounted as root.
+
+pub mod cpio;
+pub mod initramfs;
+
+use spin::Mutex;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub size: usize,
+    /// Permission bits, e.g. 0o755.
+    pub permissions: u32,
+    /// Seconds since the epoch.
+    pub mtime: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DirEntry {
+    /// Name inside the directory, without the directory's path.
+    pub name: &'static str,
+    pub file_type: FileType,
+}
+
+pub mod interface {
+    use super::{DirEntry, Metadata};
+
+    pub trait FileSystem {
+        fn name(&self) -> &'static str;
+
+        fn metadata(&self, path: &str) -> Result<Metadata, &'static str>;
+
+        /// The whole contents of the regular file at `path`.
+        fn read(&self, path: &str) -> Result<&'static [u8], &'static str>;
+
+        /// Calls `f` for every entry of the directory at `path`.
+        fn read_dir(&self, path: &str, f: &mut dyn FnMut(DirEntry)) -> Result<(), &'static str>;
+    }
+}
+
+static ROOT: Mutex<Option<&'static (dyn interface::FileSystem + Sync)>> = Mutex::new(None);
+
+pub fn mount_root(fs: &'static (dyn interface::FileSystem + Sync)) {
+    *ROOT.lock() = Some(fs);
+}
+
+/// The root file system, if one is mounted.
+pub fn root() -> Option<&'static (dyn interface::FileSystem + Sync)> {
+    *ROOT.lock()
+}
@@ -0,0 +1,213 @@
+// Boot-time initialization calls
+//
+// Subsystems register their setup functions with initcall!, which places them in one
+// `.initcall.N` section per level. `run_all` calls them level by level, in the order of `Level`,
+// and within a level in link order. Nothing can be printed until a driver registers the console,
+// so timings are recorded and printed later by `report`. Failures are logged right away as well,
+// the log buffer keeps them until there is a console.
+
+use crate::arch::memory::maintenance;
+use crate::bsp;
+use crate::bsp::memory::{physical_region_of, virtual_region_of, KERNEL_TABLES};
+use crate::memory::mmu::frame_alloc::kernel_frame_allocator;
+use crate::memory::translation_table::interface::TranslationTable;
+use crate::memory::types::{AccessPermissions, AttributeFields, MemoryAttributes};
+use crate::sync::spinlock::Spinlock;
+use core::cell::UnsafeCell;
+use core::fmt::Display;
+use core::time::Duration;
+use log::{info, warn};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Level {
+    /// Logging and other setup that does not need memory management.
+    Early,
+    /// Kernel mappings, the MMU and the physical and virtual allocators.
+    Arch,
+    /// Core kernel services drivers build on.
+    Core,
+    /// Device probing.
+    Driver,
+    /// Everything that needs devices, e.g. file systems and timers.
+    Late,
+}
+
+const LEVELS: [Level; 5] = [
+    Level::Early,
+    Level::Arch,
+    Level::Core,
+    Level::Driver,
+    Level::Late,
+];
+
+impl Display for Level {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Level::Early => write!(f, "early"),
+            Level::Arch => write!(f, "arch"),
+            Level::Core => write!(f, "core"),
+            Level::Driver => write!(f, "driver"),
+            Level::Late => write!(f, "late"),
+        }
+    }
+}
+
+pub struct Initcall {
+    pub name: &'static str,
+    pub call: fn() -> Result<(), &'static str>,
+}
+
+/// Registers `fn() -> Result<(), &'static str>` to run at boot, e.g.
+/// `initcall!(driver, init_drivers);`
+#[macro_export]
+macro_rules! initcall {
+    (@entry $section:literal, $fn:path) => {
+        const _: () = {
+            #[used]
+            #[link_section = $section]
+            static ENTRY: $crate::initcall::Initcall = $crate::initcall::Initcall {
+                name: concat!(module_path!(), "::", stringify!($fn)),
+                call: $fn,
+            };
+        };
+    };
+    (early, $fn:path) => { $crate::initcall!(@entry ".initcall.0", $fn); };
+    (arch, $fn:path) => { $crate::initcall!(@entry ".initcall.1", $fn); };
+    (core, $fn:path) => { $crate::initcall!(@entry ".initcall.2", $fn); };
+    (driver, $fn:path) => { $crate::initcall!(@entry ".initcall.3", $fn); };
+    (late, $fn:path) => { $crate::initcall!(@entry ".initcall.4", $fn); };
+}
+
+extern "Rust" {
+    static __initcall0_start_: UnsafeCell<()>;
+    static __initcall1_start_: UnsafeCell<()>;
+    static __initcall2_start_: UnsafeCell<()>;
+    static __initcall3_start_: UnsafeCell<()>;
+    static __initcall4_start_: UnsafeCell<()>;
+    static __initcall_end_: UnsafeCell<()>;
+}
+
+fn initcalls(level: Level) -> &'static [Initcall] {
+    unsafe {
+        let bounds = [
+            __initcall0_start_.get(),
+            __initcall1_start_.get(),
+            __initcall2_start_.get(),
+            __initcall3_start_.get(),
+            __initcall4_start_.get(),
+            __initcall_end_.get(),
+        ];
+        let index = level as usize;
+        let start = bounds[index] as *const Initcall;
+        let end = bounds[index + 1] as *const Initcall;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Record {
+    name: &'static str,
+    level: Level,
+    duration: Duration,
+    result: Result<(), &'static str>,
+}
+
+const MAX_RECORDS: usize = 64;
+static RECORDS: Spinlock<[Option<Record>; MAX_RECORDS]> = Spinlock::new([None; MAX_RECORDS]);
+
+/// Runs every initcall, level by level. A failing call is logged and recorded and does not stop the
+/// others.
+pub fn run_all() {
+    let mut count = 0;
+
+    for level in LEVELS {
+        for initcall in initcalls(level) {
+            let start = crate::arch::timer::uptime();
+            let result = (initcall.call)();
+            let duration = crate::arch::timer::uptime() - start;
+
+            if let Err(e) = result {
+                warn!("initcall {} {} failed: {}", level, initcall.name, e);
+            }
+            // Printed once a console is registered, until then kept in the buffer.
+            crate::console::klog::drain();
+
+            let record = Record {
+                name: initcall.name,
+                level,
+                duration,
+                result,
+            };
+            let recorded = RECORDS
+                .lock()
+                .get_mut(count)
+                .map(|slot| *slot = Some(record));
+            if recorded.is_none() && count == MAX_RECORDS {
+                warn!(
+                    "initcall: more than {} calls, not recording the rest",
+                    MAX_RECORDS
+                );
+            }
+            count += 1;
+        }
+    }
+}
+
+/// Prints how long each initcall took and which ones failed.
+pub fn report() {
+    let records = *RECORDS.lock();
+    for record in records.iter().flatten() {
+        match record.result {
+            Ok(()) => info!(
+                "initcall {: <6} {} took {}us",
+                record.level,
+                record.name,
+                record.duration.as_micros()
+            ),
+            Err(e) => warn!(
+                "initcall {: <6} {} failed after {}us: {}",
+                record.level,
+                record.name,
+                record.duration.as_micros(),
+                e
+            ),
+        }
+    }
+}
+
+/// Hands the `.init` section to the frame allocator. It is remapped non-executable first, so
+/// calling into it afterwards faults instead of running whatever the pages now hold.
+pub fn free_init() -> Result<(), &'static str> {
+    let init = bsp::memory::symbols::init();
+    let virt_region = virtual_region_of(init.range.start, init.range.end);
+    let phys_region = physical_region_of(virt_region);
+
+    KERNEL_TABLES.write().set_attributes(
+        &virt_region,
+        &AttributeFields {
+            memory_attributes: MemoryAttributes::CacheableDRAM,
+            access_permissions: AccessPermissions::RW,
+        },
+    )?;
+    maintenance::tlb_flush_va(virt_region.start_addr()..virt_region.end_addr(), None);
+
+    kernel_frame_allocator().lock().free(phys_region)?;
+    info!("Freed {} of init memory", phys_region.size());
+    Ok(())
+}
+
+/// Makes the statics in `.data.ro_after_init` read-only, so that writing them after boot faults.
+pub fn protect_ro_after_init() -> Result<(), &'static str> {
+    let section = bsp::memory::symbols::ro_after_init();
+    let virt_region = virtual_region_of(section.range.start, section.range.end);
+
+    KERNEL_TABLES.write().set_attributes(
+        &virt_region,
+        &AttributeFields {
+            access_permissions: AccessPermissions::RO,
+            ..section.attr
+        },
+    )?;
+    maintenance::tlb_flush_va(virt_region.start_addr()..virt_region.end_addr(), None);
+    Ok(())
+}
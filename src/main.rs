@@ -8,51 +8,54 @@ pub mod print;
 
 pub mod arch;
 pub mod bsp;
+pub mod cmdline;
 pub mod console;
 pub mod driver;
+pub mod fs;
+pub mod initcall;
 pub mod interrupt;
 pub mod memory;
+pub mod monitor;
+pub mod power;
 pub mod sync;
 
 extern crate log as log_crate;
 use crate::arch::exception::el::get_current_el;
 use arch::memory::mmu;
 use bsp::memory::symbols;
-use core::{alloc::Layout, arch::asm};
+use core::alloc::Layout;
 use log_crate::{debug, error, info, warn};
 
+// Dump the page tables after a panic, e.g. to see what a faulting address was mapped as.
+crate::param!(PANIC_DUMP_TABLES: bool = "panic.dump_tables", false);
+
 #[no_mangle]
-pub(crate) unsafe extern "C" fn kernel_main() -> ! {
+pub(crate) unsafe extern "C" fn kernel_main(dtb_addr: usize) -> ! {
     // Initialize Exceptions
     arch::irq::irq_disable();
     arch::exception::set_exception_handler();
 
-    console::log::init();
-
-    let phys_kernel_tables_base_addr = match memory::kernel_mapper::kernel_map_sections() {
-        Err(string) => panic!("Error mapping kernel binary: {}", string),
-        Ok(addr) => addr,
-    };
-
-    if let Err(e) = memory::mmu::init(phys_kernel_tables_base_addr) {
-        panic!("Enabling MMU failed: {}", e);
-    }
-
-    // NOTE: No printing between MMU enable and UART re-init.
-    // After MMU is on, the physical UART address 0x0900_0000 is unmapped.
-    // We must init the MMIO allocator and remap the UART first.
+    bsp::init_device_tree(dtb_addr);
+    cmdline::init();
+    arch::kaslr::randomize();
+    console::earlycon::init();
 
-    memory::mmu::init_mmio_allocator();
-    bsp::init_drivers(false);
+    // Logging, the MMU, drivers, file systems and the timer.
+    initcall::run_all();
 
-    // UART is now remapped to a virtual address — safe to print again.
     println!("MMU enabled.");
-
-    // Initialize Interrupts
-    bsp::init_irq();
-
-    // Initialize Timer Interrupt
-    arch::timer::init_irq();
+    // The software walker and the hardware have to agree, including on MMIO mapped by drivers.
+    #[cfg(debug_assertions)]
+    memory::kernel_mapper::verify_tables();
+    cmdline::report();
+    initcall::report();
+
+    if let Err(e) = initcall::free_init() {
+        warn!("Init memory not freed: {}", e);
+    }
+    if let Err(e) = initcall::protect_ro_after_init() {
+        warn!("Read-only after init data left writable: {}", e);
+    }
 
     arch::irq::irq_enable();
     arch::irq::fiq_enable();
@@ -70,6 +73,15 @@ pub(crate) unsafe extern "C" fn kernel_main() -> ! {
         symbols::kernel_range().start,
         symbols::kernel_range().end
     );
+    println!(
+        "kernel load offset: {:#x}{}",
+        arch::kaslr::load_offset(),
+        if arch::kaslr::is_randomized() {
+            " (KASLR)"
+        } else {
+            ""
+        }
+    );
 
     println!("********* MMU Status *********");
     mmu::print_stat();
@@ -87,16 +99,21 @@ pub(crate) unsafe extern "C" fn kernel_main() -> ! {
     info!("Exception handling state:");
     arch::exception::print_state();
 
+    info!("Devices:");
+    driver::print_devices();
+
     info!("Registered IRQ handlers:");
     arch::irq::print_interrupts();
 
-    info!("Echoing Inputs");
-    info!("Waiting for interrupts...");
-
-    let console = console::console();
-    console.clear_rx();
+    if let Some(input) = console::input_console() {
+        input.console.clear_rx();
+    }
+    monitor::start();
 
     loop {
+        // Print what interrupt handlers logged while a drain was in progress.
+        console::klog::drain();
+        monitor::poll();
         arch::halt();
     }
 }
@@ -108,6 +125,16 @@ fn handle_alloc_error(_layout: Layout) -> ! {
 
 #[panic_handler]
 fn handle_panic(info: &core::panic::PanicInfo<'_>) -> ! {
+    // Output goes straight to the UART from here on, and the other cores are stopped.
+    if !console::emergency::enter() {
+        println!("KERNEL PANIC while panicking: {}", info.message());
+        power::shutdown(power::EXIT_FAILURE)
+    }
+
+    // Show the messages leading up to the panic first. The whole log stays available through
+    // `console::klog::replay`.
+    console::klog::force_drain();
+
     println!("************************************************");
     println!("KERNEL PANIC: {}", info.message());
     let (file, line, column) = match info.location() {
@@ -116,29 +143,13 @@ fn handle_panic(info: &core::panic::PanicInfo<'_>) -> ! {
     };
 
     println!("{}:{}:{}", file, line, column);
+    println!("Kernel load offset: {:#x}", arch::kaslr::load_offset());
     println!("************************************************");
 
-    #[repr(C)]
-    struct QEMUParameterBlock {
-        arg0: u64,
-        arg1: u64,
+    if PANIC_DUMP_TABLES.get() {
+        println!("Kernel page tables:");
+        memory::kernel_mapper::print_tables();
     }
 
-    let block = &QEMUParameterBlock {
-        arg0: 0x20026,
-        arg1: 1,
-    };
-
-    unsafe {
-        asm!(
-            "hlt #0xF000",
-            in("x0") 0x18,
-            in("x1") block as *const _ as u64,
-            options(nostack)
-        );
-    }
-
-    loop {
-        unsafe { asm!("wfe", options(nomem, nostack)) };
-    }
+    power::shutdown(power::EXIT_FAILURE)
 }
@@ -1,4 +1,5 @@
 use super::translation_table::interface::TranslationTable;
+use super::translation_table::walk;
 use crate::bsp;
 use crate::bsp::memory::{physical_region_of, virtual_region_of};
 use crate::memory::types::*;
@@ -48,3 +49,24 @@ pub fn log_mapping() {
     }
     println!("      -------------------------------------------------------------------------------------------------------------------------------------------");
 }
+
+/// Prints what is mapped in the kernel tables, including MMIO mappings made after boot.
+pub fn print_tables() {
+    match bsp::memory::KERNEL_TABLES.try_read() {
+        Some(kernel_table) => walk::print_mappings(&*kernel_table),
+        // The holder is stopped or is the panicking code itself. A table caught halfway through a
+        // change still holds valid descriptors, so it is read as it is.
+        None if crate::console::emergency::is_active() => {
+            walk::print_mappings(unsafe { &*bsp::memory::KERNEL_TABLES.as_mut_ptr() })
+        }
+        None => {
+            println!("      Kernel tables are being changed, try again");
+        }
+    }
+}
+
+/// Checks the kernel tables against what the MMU translates their pages to.
+pub fn verify_tables() {
+    let kernel_table = bsp::memory::KERNEL_TABLES.read();
+    crate::arch::memory::mmu::translate::check_table(&*kernel_table);
+}
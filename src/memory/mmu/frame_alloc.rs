@@ -0,0 +1,134 @@
+use super::{Address, MemoryRegion, PageAddress, Physical};
+use crate::bsp;
+use core::num::NonZeroUsize;
+use spin::Mutex;
+
+const MAX_RESERVATIONS: usize = 16;
+const MAX_FREED: usize = 16;
+
+static KERNEL_FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
+
+pub fn kernel_frame_allocator() -> &'static Mutex<FrameAllocator> {
+    &KERNEL_FRAME_ALLOCATOR
+}
+
+/// A physical range that must not be handed out, e.g. the kernel image or the initrd.
+#[derive(Copy, Clone)]
+pub struct Reservation {
+    pub name: &'static str,
+    pub region: MemoryRegion<Physical>,
+}
+
+/// Hands out physical page frames from RAM, skipping reserved ranges. Frames given back with
+/// `free` are handed out again before the rest of the pool.
+pub struct FrameAllocator {
+    pool: Option<MemoryRegion<Physical>>,
+    reserved: [Option<Reservation>; MAX_RESERVATIONS],
+    freed: [Option<MemoryRegion<Physical>>; MAX_FREED],
+}
+
+impl Default for FrameAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameAllocator {
+    pub const fn new() -> Self {
+        Self {
+            pool: None,
+            reserved: [None; MAX_RESERVATIONS],
+            freed: [None; MAX_FREED],
+        }
+    }
+
+    pub fn init(&mut self, pool: MemoryRegion<Physical>) {
+        self.pool = Some(pool);
+    }
+
+    /// Keeps the pages covering `start_addr..end_addr` out of future allocations.
+    pub fn reserve(
+        &mut self,
+        name: &'static str,
+        start_addr: Address<Physical>,
+        end_addr: Address<Physical>,
+    ) -> Result<(), &'static str> {
+        let region = MemoryRegion::new(
+            PageAddress::from(start_addr.align_down_page()),
+            PageAddress::from(end_addr.align_up_page()),
+        );
+
+        let slot = self
+            .reserved
+            .iter_mut()
+            .find(|reservation| reservation.is_none())
+            .ok_or("Too many reserved regions")?;
+        *slot = Some(Reservation { name, region });
+        Ok(())
+    }
+
+    pub fn reservations(&self) -> impl Iterator<Item = &Reservation> {
+        self.reserved.iter().flatten()
+    }
+
+    /// Makes `region` available for allocation again, e.g. the kernel's init sections once boot
+    /// is done.
+    pub fn free(&mut self, region: MemoryRegion<Physical>) -> Result<(), &'static str> {
+        let slot = self
+            .freed
+            .iter_mut()
+            .find(|freed| freed.is_none())
+            .ok_or("Too many freed regions")?;
+        *slot = Some(region);
+        Ok(())
+    }
+
+    pub fn alloc(
+        &mut self,
+        num_pages: NonZeroUsize,
+    ) -> Result<MemoryRegion<Physical>, &'static str> {
+        let size = usize::from(num_pages) * bsp::memory::KernelGranule::SIZE;
+
+        for slot in self.freed.iter_mut() {
+            let Some(freed) = slot else {
+                continue;
+            };
+            if freed.size().0 < size {
+                continue;
+            }
+
+            let start = freed.start_addr().value();
+            let allocation =
+                MemoryRegion::new(PageAddress::from(start), PageAddress::from(start + size));
+            freed.set_start_page(allocation.end_page_addr());
+            if freed.size().0 == 0 {
+                *slot = None;
+            }
+            return Ok(allocation);
+        }
+
+        let pool = self.pool.as_mut().expect("Allocator not initialized");
+
+        let mut start = pool.start_addr().value();
+        loop {
+            let end = start.checked_add(size).ok_or("Out of physical memory")?;
+            if end > pool.end_addr().value() {
+                return Err("Out of physical memory");
+            }
+
+            // Skip past the first reservation in the way and try again.
+            match self.reserved.iter().flatten().find(|reservation| {
+                start < reservation.region.end_addr().value()
+                    && reservation.region.start_addr().value() < end
+            }) {
+                Some(reservation) => start = reservation.region.end_addr().value(),
+                None => break,
+            }
+        }
+
+        let allocation =
+            MemoryRegion::new(PageAddress::from(start), PageAddress::from(start + size));
+        pool.set_start_page(allocation.end_page_addr());
+        Ok(allocation)
+    }
+}
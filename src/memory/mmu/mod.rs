@@ -1,9 +1,11 @@
 pub mod error;
+pub mod frame_alloc;
 pub mod interface;
 pub mod page_alloc;
 
 pub use error::*;
 
+use crate::arch::drivers::devicetree;
 use crate::{arch, bsp};
 use interface::MMU;
 
@@ -13,16 +15,50 @@ pub fn init(phys_table_baddr: Address<Physical>) -> Result<(), self::error::MMUE
     arch::memory::mmu().init(phys_table_baddr)
 }
 
-pub fn init_mmio_allocator() {
-    let region = {
-        let mmio_section = bsp::memory::symbols::mmio_remap_range();
-        let start_addr = mmio_section.start.into_virtual();
-        let end_addr = mmio_section.end.into_virtual();
+/// Sets up the virtual address windows that MMIO and RAM are mapped into after boot.
+pub fn init_va_allocators() {
+    let region = |section: core::ops::Range<Address<Physical>>| {
+        let start_addr = section.start.into_virtual();
+        let end_addr = section.end.into_virtual();
         let start_page_addr: PageAddress<Virtual> = PageAddress::from(start_addr);
         let end_page_addr: PageAddress<Virtual> = PageAddress::from(end_addr);
 
         MemoryRegion::new(start_page_addr, end_page_addr)
     };
 
-    page_alloc::kernel_va_allocator().lock().init(region)
+    page_alloc::kernel_va_allocator()
+        .lock()
+        .init(region(bsp::memory::symbols::mmio_remap_range()));
+    page_alloc::kernel_ram_va_allocator()
+        .lock()
+        .init(region(bsp::memory::symbols::ram_remap_range()));
+}
+
+/// Hands all RAM to the frame allocator, except for the kernel image, the device tree blob, the
+/// initrd and the memory the device tree reserves.
+pub fn init_frame_allocator() -> Result<(), &'static str> {
+    let (ram_start, ram_size) = arch::memory::get_ramrange();
+    let ram_start = ram_start as usize;
+    let region = MemoryRegion::new(
+        PageAddress::from(Address::<Physical>::new(ram_start).align_up_page()),
+        PageAddress::from(Address::<Physical>::new(ram_start + ram_size.0).align_down_page()),
+    );
+
+    let mut allocator = frame_alloc::kernel_frame_allocator().lock();
+    allocator.init(region);
+
+    let image = bsp::memory::symbols::image_range();
+    allocator.reserve("Kernel", image.start, image.end)?;
+
+    let blob = devicetree::blob_range();
+    allocator.reserve("Device Tree", blob.start.into(), blob.end.into())?;
+
+    if let Some(initrd) = devicetree::initrd_range() {
+        allocator.reserve("initrd", initrd.start.into(), initrd.end.into())?;
+    }
+
+    for range in devicetree::reserved_memory() {
+        allocator.reserve("Firmware", range.start.into(), range.end.into())?;
+    }
+    Ok(())
 }
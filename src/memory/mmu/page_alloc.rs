@@ -4,11 +4,18 @@ use core::num::NonZeroUsize;
 use spin::Mutex;
 
 static KERNEL_VA_ALLOCATOR: Mutex<PageAllocator<Virtual>> = Mutex::new(PageAllocator::new());
+static KERNEL_RAM_VA_ALLOCATOR: Mutex<PageAllocator<Virtual>> = Mutex::new(PageAllocator::new());
 
+/// Virtual addresses for MMIO.
 pub fn kernel_va_allocator() -> &'static Mutex<PageAllocator<Virtual>> {
     &KERNEL_VA_ALLOCATOR
 }
 
+/// Virtual addresses for RAM mapped after boot, such as the initrd.
+pub fn kernel_ram_va_allocator() -> &'static Mutex<PageAllocator<Virtual>> {
+    &KERNEL_RAM_VA_ALLOCATOR
+}
+
 pub struct PageAllocator<T: AddressType> {
     pool: Option<MemoryRegion<T>>,
 }
@@ -27,6 +34,9 @@ impl<T: AddressType> PageAllocator<T> {
         let pool = self.pool.as_mut().expect("Allocator not initialized");
 
         let delta: usize = num_pages * bsp::memory::KernelGranule::SIZE;
+        if delta > pool.size().0 {
+            return Err("Out of virtual address space");
+        }
         let left_end_addr = Address::new(pool.start_page_addr().value() + delta);
         let left_end_page = PageAddress::new(left_end_addr);
 
@@ -4,3 +4,25 @@ pub mod translation_table;
 pub mod address_space;
 pub mod align;
 pub mod kernel_mapper;
+
+/// Maps the kernel, turns on the MMU and sets up the allocators.
+#[link_section = ".init.text"]
+fn init() -> Result<(), &'static str> {
+    let phys_kernel_tables_base_addr = match kernel_mapper::kernel_map_sections() {
+        Err(string) => panic!("Error mapping kernel binary: {}", string),
+        Ok(addr) => addr,
+    };
+
+    // The early console's UART is unmapped once the MMU is on, so map it beforehand.
+    mmu::init_va_allocators();
+    crate::console::earlycon::map();
+
+    if let Err(e) = mmu::init(phys_kernel_tables_base_addr) {
+        panic!("Enabling MMU failed: {}", e);
+    }
+    crate::console::earlycon::mmu_enabled();
+
+    mmu::init_frame_allocator()
+}
+
+crate::initcall!(arch, init);
@@ -1,6 +1,10 @@
 use crate::memory::types::*;
+use core::fmt;
 
 pub trait TranslationTable {
+    /// The attributes of a page as the hardware sees them.
+    type PageAttributes: Copy + Eq + fmt::Display;
+
     fn init(&mut self);
     fn phys_base_addr(&self) -> Result<Address<Physical>, &'static str>;
     fn map_at(
@@ -9,4 +13,17 @@ pub trait TranslationTable {
         phys_region: &MemoryRegion<Physical>,
         attributes: &AttributeFields,
     ) -> Result<(), &'static str>;
+
+    /// Changes the attributes of pages that are mapped already, keeping what they map to.
+    fn set_attributes(
+        &mut self,
+        virt_region: &MemoryRegion<Virtual>,
+        attributes: &AttributeFields,
+    ) -> Result<(), &'static str>;
+
+    /// Calls `f` with every mapped page, in ascending order of virtual addresses.
+    fn for_each_page(
+        &self,
+        f: &mut dyn FnMut(PageAddress<Virtual>, PageAddress<Physical>, Self::PageAttributes),
+    );
 }
@@ -0,0 +1,77 @@
+// Translation table walker
+//
+// Walks what is actually mapped in a translation table, as opposed to the list of sections the
+// kernel meant to map, and merges neighbouring pages into one mapping when they continue each
+// other physically and carry the same attributes.
+
+use super::interface::TranslationTable;
+use crate::bsp::memory::KernelGranule;
+use crate::memory::types::*;
+
+/// Pages mapped contiguously with the same attributes.
+#[derive(Copy, Clone)]
+pub struct Mapping<A> {
+    pub virt_region: MemoryRegion<Virtual>,
+    pub phys_region: MemoryRegion<Physical>,
+    pub attributes: A,
+}
+
+impl<A: Eq> Mapping<A> {
+    /// Extends the mapping by the page at `virt`, if that continues it.
+    fn extend(
+        &mut self,
+        virt: PageAddress<Virtual>,
+        phys: PageAddress<Physical>,
+        attributes: A,
+    ) -> bool {
+        if virt != self.virt_region.end_page_addr()
+            || phys != self.phys_region.end_page_addr()
+            || attributes != self.attributes
+        {
+            return false;
+        }
+        self.virt_region.set_end_page(virt.offset(1).unwrap());
+        self.phys_region.set_end_page(phys.offset(1).unwrap());
+        true
+    }
+}
+
+/// Calls `f` with every mapping in `table`, in ascending order of virtual addresses.
+pub fn for_each_mapping<T: TranslationTable + ?Sized>(
+    table: &T,
+    mut f: impl FnMut(&Mapping<T::PageAttributes>),
+) {
+    let mut current: Option<Mapping<T::PageAttributes>> = None;
+    table.for_each_page(&mut |virt, phys, attributes| {
+        if let Some(mapping) = current.as_mut() {
+            if mapping.extend(virt, phys, attributes) {
+                return;
+            }
+            f(mapping);
+        }
+        current = Some(Mapping {
+            virt_region: MemoryRegion::new(virt, virt.offset(1).unwrap()),
+            phys_region: MemoryRegion::new(phys, phys.offset(1).unwrap()),
+            attributes,
+        });
+    });
+    if let Some(mapping) = current {
+        f(&mapping);
+    }
+}
+
+/// Prints the mappings in `table`. Takes no locks, so it can be used from the panic handler.
+pub fn print_mappings<T: TranslationTable + ?Sized>(table: &T) {
+    let mut pages = 0;
+    for_each_mapping(table, |mapping| {
+        println!(
+            "      {} --> {} | {} | {}",
+            mapping.virt_region,
+            mapping.phys_region,
+            mapping.virt_region.size(),
+            mapping.attributes
+        );
+        pages += mapping.virt_region.size().0 / KernelGranule::SIZE;
+    });
+    println!("      {} pages mapped", pages);
+}
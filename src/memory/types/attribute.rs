@@ -6,17 +6,35 @@ pub struct AttributeFields {
     pub access_permissions: AccessPermissions,
 }
 
+#[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug, Eq, PartialOrd, PartialEq)]
 pub enum MemoryAttributes {
+    /// Normal memory, write-back cached.
     CacheableDRAM,
+    /// Normal memory, write-through cached, e.g. a framebuffer that is mostly written.
+    WriteThrough,
+    /// Normal memory, not cached, e.g. DMA rings shared with devices that do not snoop caches.
+    NormalNonCacheable,
+    /// Device memory, nGnRE: no gathering or reordering, writes may be acknowledged early.
     Device,
+    /// Device memory, nGnRnE: strongly ordered, every write waits for the device.
+    Device_nGnRnE,
+    /// Device memory, GRE: accesses may be gathered and reordered.
+    Device_GRE,
+    /// Write-back cached like `CacheableDRAM`, and made read-only once the initcalls have run.
+    ReadOnlyAfterInit,
 }
 
 impl Display for MemoryAttributes {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let attr = match self {
             MemoryAttributes::CacheableDRAM => "C",
+            MemoryAttributes::WriteThrough => "WT",
+            MemoryAttributes::NormalNonCacheable => "NC",
             MemoryAttributes::Device => "Dev",
+            MemoryAttributes::Device_nGnRnE => "SO",
+            MemoryAttributes::Device_GRE => "GRE",
+            MemoryAttributes::ReadOnlyAfterInit => "RoI",
         };
         write!(f, "{}", attr)
     }
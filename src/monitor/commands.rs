@@ -0,0 +1,322 @@
+// Built-in monitor commands
+
+use super::{commands, parse_number, Args};
+use crate::arch::drivers::devicetree::{Node, Property};
+use crate::arch::exception::el::get_current_el;
+use crate::console::{self, klog, log};
+use crate::memory::types::Address;
+use crate::{arch, memory, power};
+use log_crate::LevelFilter;
+
+crate::command!(HELP_COMMAND = "help", "", "List the commands", help);
+crate::command!(
+    MMU_COMMAND = "mmu",
+    "",
+    "Show the MMU features and configuration",
+    mmu
+);
+crate::command!(MAP_COMMAND = "map", "", "Show the kernel sections", map);
+crate::command!(
+    TABLES_COMMAND = "tables",
+    "",
+    "Show what is mapped in the kernel page tables",
+    tables
+);
+crate::command!(
+    AT_COMMAND = "at",
+    "<addr> [r|w|ur|uw]",
+    "Translate an address with the MMU, as an EL1 or EL0 read or write",
+    at
+);
+crate::command!(
+    IRQS_COMMAND = "irqs",
+    "",
+    "List the registered interrupts",
+    irqs
+);
+crate::command!(
+    TIMER_COMMAND = "timer",
+    "",
+    "Show the timer state and uptime",
+    timer
+);
+crate::command!(
+    EL_COMMAND = "el",
+    "",
+    "Show the current exception level",
+    el
+);
+crate::command!(DT_COMMAND = "dt", "[path]", "Dump the device tree", dt);
+crate::command!(
+    MEM_COMMAND = "mem",
+    "rd <addr> [count] | wr <addr> <value>",
+    "Read or write 32-bit words at a mapped address",
+    mem
+);
+crate::command!(
+    LOG_COMMAND = "log",
+    "[level [module]] | filter <spec> | color <on|off>",
+    "Show the kernel log, or change the filter or colours",
+    log
+);
+crate::command!(
+    CONSOLE_COMMAND = "console",
+    "[<name> level <level> | <name> input]",
+    "List the consoles, or set one's log level or read from it",
+    console_command
+);
+crate::command!(REBOOT_COMMAND = "reboot", "", "Reset the system", reboot);
+crate::command!(
+    POWEROFF_COMMAND = "poweroff",
+    "",
+    "Power the system off",
+    poweroff
+);
+
+fn help(_: &mut Args<'_>) -> Result<(), &'static str> {
+    for command in commands() {
+        println!(
+            "  {: <9}{: <50} {}",
+            command.name, command.usage, command.help
+        );
+    }
+    Ok(())
+}
+
+fn mmu(_: &mut Args<'_>) -> Result<(), &'static str> {
+    if let Err(e) = arch::memory::mmu::print_stat() {
+        println!("{}", e);
+    }
+    Ok(())
+}
+
+fn map(_: &mut Args<'_>) -> Result<(), &'static str> {
+    memory::kernel_mapper::log_mapping();
+    Ok(())
+}
+
+fn tables(_: &mut Args<'_>) -> Result<(), &'static str> {
+    memory::kernel_mapper::print_tables();
+    Ok(())
+}
+
+fn at(args: &mut Args<'_>) -> Result<(), &'static str> {
+    use arch::memory::mmu::AccessKind;
+
+    let usage = "Usage: at <addr> [r|w|ur|uw]";
+    let addr = parse_number(args.next().ok_or(usage)?)?;
+    let kind = match args.next().unwrap_or("r") {
+        "r" => AccessKind::Read,
+        "w" => AccessKind::Write,
+        "ur" => AccessKind::UserRead,
+        "uw" => AccessKind::UserWrite,
+        _ => return Err(usage),
+    };
+    match arch::memory::mmu::hw_query(Address::new(addr), kind) {
+        Ok(translation) => {
+            println!("{:#x} -> {} ({})", addr, translation, kind);
+        }
+        Err(fault) => {
+            println!("{:#x}: {} ({})", addr, fault, kind);
+        }
+    }
+    Ok(())
+}
+
+fn irqs(_: &mut Args<'_>) -> Result<(), &'static str> {
+    arch::irq::print_interrupts();
+    Ok(())
+}
+
+fn timer(_: &mut Args<'_>) -> Result<(), &'static str> {
+    arch::timer::print_timer_status();
+    let uptime = arch::timer::uptime();
+    println!(
+        "uptime {}.{:06}s, resolution {}ns",
+        uptime.as_secs(),
+        uptime.subsec_micros(),
+        arch::timer::resolution().as_nanos()
+    );
+    Ok(())
+}
+
+fn el(_: &mut Args<'_>) -> Result<(), &'static str> {
+    println!("{}", get_current_el());
+    Ok(())
+}
+
+/// Prints a property value as strings, cells or bytes, whichever it looks like.
+fn print_value(property: &Property) {
+    let value = property.value;
+    let printable = |s: &[u8]| !s.is_empty() && s.iter().all(|c| (0x20..0x7f).contains(c));
+    if value.is_empty() {
+        println!(";");
+    } else if value.last() == Some(&0) && value[..value.len() - 1].split(|&c| c == 0).all(printable)
+    {
+        print!(" =");
+        for (index, string) in property.strings().enumerate() {
+            print!("{} \"{}\"", if index > 0 { "," } else { "" }, string);
+        }
+        println!(";");
+    } else if value.len().is_multiple_of(4) {
+        print!(" = <");
+        for (index, cell) in property.cells().enumerate() {
+            print!("{}{:#x}", if index > 0 { " " } else { "" }, cell);
+        }
+        println!(">;");
+    } else {
+        print!(" = [");
+        for (index, byte) in value.iter().enumerate() {
+            print!("{}{:02x}", if index > 0 { " " } else { "" }, byte);
+        }
+        println!("];");
+    }
+}
+
+fn print_node(node: Node, depth: usize) {
+    let indent = depth * 4;
+    let name = if depth == 0 && node == Node::root() {
+        "/"
+    } else {
+        node.name()
+    };
+    println!("{:indent$}{} {{", "", name, indent = indent);
+    for property in node.properties() {
+        print!("{:indent$}{}", "", property.name, indent = indent + 4);
+        print_value(&property);
+    }
+    for child in node.children() {
+        print_node(child, depth + 1);
+    }
+    println!("{:indent$}}};", "", indent = indent);
+}
+
+fn dt(args: &mut Args<'_>) -> Result<(), &'static str> {
+    let path = args.next().unwrap_or("/");
+    let node = Node::find(path).ok_or("No such node")?;
+    print_node(node, 0);
+    Ok(())
+}
+
+const MAX_WORDS: usize = 256;
+
+/// Address of the `index`th word from `addr`, checked to be mapped for an access of `kind` so that
+/// a typo is reported instead of taking a data abort.
+fn word_addr(
+    addr: usize,
+    index: usize,
+    kind: arch::memory::mmu::AccessKind,
+) -> Result<usize, &'static str> {
+    let addr = index
+        .checked_mul(4)
+        .and_then(|offset| addr.checked_add(offset))
+        .ok_or("Address out of range")?;
+    arch::memory::mmu::hw_translate(Address::new(addr), kind).map_err(|_| match kind {
+        arch::memory::mmu::AccessKind::Write => "Address is not mapped writable",
+        _ => "Address is not mapped",
+    })?;
+    Ok(addr)
+}
+
+fn mem(args: &mut Args<'_>) -> Result<(), &'static str> {
+    use arch::memory::mmu::AccessKind;
+
+    let usage = "Usage: mem rd <addr> [count] | mem wr <addr> <value>";
+    let operation = args.next().ok_or(usage)?;
+    let addr = parse_number(args.next().ok_or(usage)?)?;
+    if addr % 4 != 0 {
+        return Err("Address must be 4-byte aligned");
+    }
+
+    match operation {
+        "rd" => {
+            let count = args.next().map_or(Ok(1), parse_number)?.min(MAX_WORDS);
+            // Check the whole range first so that a dump is not cut off halfway through a line.
+            for index in 0..count {
+                word_addr(addr, index, AccessKind::Read)?;
+            }
+            for index in 0..count {
+                let addr = addr + index * 4;
+                if index % 4 == 0 {
+                    print!("{:#018x}:", addr);
+                }
+                let word = unsafe { core::ptr::read_volatile(addr as *const u32) };
+                print!(" {:08x}", word);
+                if index % 4 == 3 || index == count - 1 {
+                    println!();
+                }
+            }
+        }
+        "wr" => {
+            let value = parse_number(args.next().ok_or(usage)?)?;
+            let value = u32::try_from(value).map_err(|_| "Value does not fit in 32 bits")?;
+            let addr = word_addr(addr, 0, AccessKind::Write)?;
+            unsafe { core::ptr::write_volatile(addr as *mut u32, value) };
+        }
+        _ => return Err(usage),
+    }
+    Ok(())
+}
+
+fn log(args: &mut Args<'_>) -> Result<(), &'static str> {
+    match args.next() {
+        Some("filter") => log::set_filter(args.next().ok_or("Missing filter spec")?),
+        Some("color") => {
+            match args.next() {
+                Some("on") => log::set_color(true),
+                Some("off") => log::set_color(false),
+                _ => return Err("Usage: log color <on|off>"),
+            }
+            Ok(())
+        }
+        level => {
+            let level = match level {
+                Some(level) => level.parse().map_err(|_| "Invalid log level")?,
+                None => LevelFilter::Trace,
+            };
+            klog::replay(level, args.next().unwrap_or(""));
+            Ok(())
+        }
+    }
+}
+
+fn console_command(args: &mut Args<'_>) -> Result<(), &'static str> {
+    let usage = "Usage: console [<name> level <level> | <name> input]";
+    let Some(name) = args.next() else {
+        let input = console::input_console().map(|sink| sink.name);
+        for sink in console::consoles() {
+            let input = if input == Some(sink.name) {
+                " input"
+            } else {
+                ""
+            };
+            println!(
+                "  {: <12}{: <6} written {} read {}{}",
+                sink.name,
+                sink.level,
+                sink.console.chars_written(),
+                sink.console.chars_read(),
+                input
+            );
+        }
+        return Ok(());
+    };
+
+    match args.next() {
+        Some("level") => {
+            let level = args.next().ok_or(usage)?;
+            let level = level.parse().map_err(|_| "Invalid log level")?;
+            console::set_console_level(name, level)
+        }
+        Some("input") => console::set_input_console(name),
+        _ => Err(usage),
+    }
+}
+
+fn reboot(_: &mut Args<'_>) -> Result<(), &'static str> {
+    power::reboot()
+}
+
+fn poweroff(_: &mut Args<'_>) -> Result<(), &'static str> {
+    power::shutdown(power::EXIT_SUCCESS)
+}
@@ -0,0 +1,261 @@
+// Monitor shell
+//
+// A small command shell on the console for looking at a running kernel. Commands are declared with
+// command!, which places them in the `.commands` linker section, so any subsystem can add its own.
+// The shell runs from the idle loop: `poll` takes what the TTY has received and edits the line,
+// with cursor keys, Backspace/Delete, Ctrl-A/Ctrl-E and a history browsed with Up and Down.
+
+use crate::console;
+use crate::console::tty::{self, Input, Key};
+use crate::sync::spinlock::Spinlock;
+use core::cell::UnsafeCell;
+
+mod commands;
+
+const PROMPT: &str = "cosmos> ";
+const LINE_SIZE: usize = 128;
+const HISTORY_SIZE: usize = 16;
+
+pub type Args<'a> = core::str::SplitWhitespace<'a>;
+
+pub struct Command {
+    pub name: &'static str,
+    /// The arguments taken, e.g. `<addr> [count]`.
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub run: fn(&mut Args<'_>) -> Result<(), &'static str>,
+}
+
+/// Registers a monitor command, e.g.
+/// `command!(HELP_COMMAND = "help", "", "List the commands", help);`
+#[macro_export]
+macro_rules! command {
+    ($ident:ident = $name:literal, $usage:literal, $help:literal, $run:expr) => {
+        #[used]
+        #[link_section = ".commands"]
+        static $ident: $crate::monitor::Command = $crate::monitor::Command {
+            name: $name,
+            usage: $usage,
+            help: $help,
+            run: $run,
+        };
+    };
+}
+
+pub fn commands() -> &'static [Command] {
+    extern "Rust" {
+        static __commands_start_: UnsafeCell<()>;
+        static __commands_end_: UnsafeCell<()>;
+    }
+
+    unsafe {
+        let start = __commands_start_.get() as *const Command;
+        let end = __commands_end_.get() as *const Command;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+pub fn parse_number(arg: &str) -> Result<usize, &'static str> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => arg.parse(),
+    }
+    .map_err(|_| "Invalid number")
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Line {
+    chars: [char; LINE_SIZE],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Line = Line {
+        chars: ['\0'; LINE_SIZE],
+        len: 0,
+    };
+
+    fn chars(&self) -> &[char] {
+        &self.chars[..self.len]
+    }
+
+    /// The line as UTF-8, in `buf`.
+    fn encode<'a>(&self, buf: &'a mut [u8; LINE_SIZE * 4]) -> &'a str {
+        let mut len = 0;
+        for c in self.chars() {
+            len += c.encode_utf8(&mut buf[len..]).len();
+        }
+        core::str::from_utf8(&buf[..len]).unwrap()
+    }
+}
+
+struct Editor {
+    line: Line,
+    cursor: usize,
+    /// Earlier lines, the newest at `history_len - 1` once wrapped around `HISTORY_SIZE`.
+    history: [Line; HISTORY_SIZE],
+    history_len: usize,
+    /// How far back in the history Up has gone, 0 while editing a new line.
+    browsing: usize,
+    /// The new line, kept while browsing the history.
+    saved: Line,
+}
+
+impl Editor {
+    const fn new() -> Self {
+        Self {
+            line: Line::EMPTY,
+            cursor: 0,
+            history: [Line::EMPTY; HISTORY_SIZE],
+            history_len: 0,
+            browsing: 0,
+            saved: Line::EMPTY,
+        }
+    }
+
+    /// The line `back` entries before the newest, starting at 1.
+    fn history_entry(&self, back: usize) -> Line {
+        self.history[(self.history_len - back) % HISTORY_SIZE]
+    }
+
+    fn remember(&mut self, line: Line) {
+        if self.history_len > 0 && self.history_entry(1) == line {
+            return;
+        }
+        self.history[self.history_len % HISTORY_SIZE] = line;
+        self.history_len += 1;
+    }
+
+    fn browse(&mut self, back: usize) {
+        if back > self.history_len.min(HISTORY_SIZE) {
+            return;
+        }
+        if self.browsing == 0 {
+            self.saved = self.line;
+        }
+        self.browsing = back;
+        self.line = match back {
+            0 => self.saved,
+            back => self.history_entry(back),
+        };
+        self.cursor = self.line.len;
+    }
+
+    fn insert(&mut self, c: char) {
+        if self.line.len == LINE_SIZE {
+            return;
+        }
+        self.line
+            .chars
+            .copy_within(self.cursor..self.line.len, self.cursor + 1);
+        self.line.chars[self.cursor] = c;
+        self.line.len += 1;
+        self.cursor += 1;
+    }
+
+    fn remove(&mut self, index: usize) {
+        if index >= self.line.len {
+            return;
+        }
+        self.line.chars.copy_within(index + 1..self.line.len, index);
+        self.line.len -= 1;
+    }
+
+    fn redraw(&self) {
+        print!("\r{}", PROMPT);
+        for c in self.line.chars() {
+            print!("{}", c);
+        }
+        // Clear what is left of a longer line and put the cursor back.
+        print!("\x1b[K");
+        if self.cursor < self.line.len {
+            print!("\x1b[{}D", self.line.len - self.cursor);
+        }
+    }
+
+    /// Takes one input. Returns the line to run once Enter is pressed.
+    fn handle(&mut self, input: Input) -> Option<Line> {
+        match input {
+            Input::Char('\n') => {
+                println!();
+                let line = self.line;
+                self.line = Line::EMPTY;
+                self.cursor = 0;
+                self.browsing = 0;
+                if line.len > 0 {
+                    self.remember(line);
+                }
+                return Some(line);
+            }
+            Input::Interrupt => {
+                println!("^C");
+                self.line = Line::EMPTY;
+                self.cursor = 0;
+                self.browsing = 0;
+            }
+            Input::Char('\x7f' | '\x08') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.remove(self.cursor);
+            }
+            Input::Key(Key::Delete) => self.remove(self.cursor),
+            Input::Key(Key::Left) if self.cursor > 0 => self.cursor -= 1,
+            Input::Key(Key::Right) if self.cursor < self.line.len => self.cursor += 1,
+            Input::Key(Key::Home) | Input::Char('\x01') => self.cursor = 0,
+            Input::Key(Key::End) | Input::Char('\x05') => self.cursor = self.line.len,
+            Input::Key(Key::Up) => self.browse(self.browsing + 1),
+            Input::Key(Key::Down) if self.browsing > 0 => self.browse(self.browsing - 1),
+            Input::Char(c) if !c.is_control() => self.insert(c),
+            _ => return None,
+        }
+        self.redraw();
+        None
+    }
+}
+
+static EDITOR: Spinlock<Editor> = Spinlock::new(Editor::new());
+
+fn run(line: &str) {
+    let mut args = line.split_whitespace();
+    let Some(name) = args.next() else {
+        return;
+    };
+
+    match commands().iter().find(|command| command.name == name) {
+        Some(command) => {
+            if let Err(e) = (command.run)(&mut args) {
+                println!("{}: {}", name, e);
+            }
+        }
+        None => {
+            println!("{}: unknown command, try `help`", name);
+        }
+    }
+}
+
+/// Takes over the console input and shows the prompt.
+pub fn start() {
+    // The editor echoes and handles Enter itself.
+    console::tty().set_mode(tty::Mode {
+        canonical: false,
+        echo: false,
+        signals: true,
+        decode_keys: true,
+        cr_to_nl: true,
+    });
+    println!("Monitor ready, type `help` for the commands.");
+    print!("{}", PROMPT);
+}
+
+/// Handles the input received since the last call, running commands as lines are completed.
+pub fn poll() {
+    while let Some(input) = console::tty().read() {
+        // Released while the command runs.
+        let line = EDITOR.lock().handle(input);
+        if let Some(line) = line {
+            let mut buf = [0; LINE_SIZE * 4];
+            run(line.encode(&mut buf));
+            print!("{}", PROMPT);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// System power management
+//
+// PSCI is the primary mechanism. Builds with the `semihosting` feature, which run under QEMU with
+// a semihosting host, exit through the host first because PSCI SYSTEM_OFF has no way to report
+// the exit code. Elsewhere that call would trap, so it is left out.
+
+use crate::arch::psci;
+use log::{error, warn};
+
+pub const EXIT_SUCCESS: u32 = 0;
+pub const EXIT_FAILURE: u32 = 1;
+
+fn semihosting_exit(code: u32) {
+    #[cfg(feature = "semihosting")]
+    crate::arch::semihosting::semihosting_exit(code as u64);
+}
+
+fn park() -> ! {
+    loop {
+        crate::arch::halt();
+    }
+}
+
+/// Powers the system off and, under emulation, reports `code` as the exit status.
+pub fn shutdown(code: u32) -> ! {
+    semihosting_exit(code);
+
+    let err = psci::system_off();
+    warn!("PSCI SYSTEM_OFF failed: {}", err);
+
+    error!("Unable to power off, halting");
+    park()
+}
+
+/// Resets the system.
+pub fn reboot() -> ! {
+    let err = psci::system_reset();
+    warn!("PSCI SYSTEM_RESET failed: {}", err);
+
+    // Semihosting has no reset, so the best we can do under emulation is to stop.
+    semihosting_exit(EXIT_FAILURE);
+
+    error!("Unable to reset, halting");
+    park()
+}
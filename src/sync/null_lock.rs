@@ -61,7 +61,7 @@ impl<T> super::interface::Mutex for IRQSafeNullLock<T> {
         // mutable reference will ever only be given out once at a time.
         let data = unsafe { &mut *self.data.get() };
 
-        // TODO: Implement IRQ Safe lock
+        // Only keeps out interrupt handlers on the calling core.
         irq::exec_with_irq_disabled(|| f(data))
     }
 }